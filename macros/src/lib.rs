@@ -0,0 +1,141 @@
+//! Procedural macros that apply stack effect diagrams with [autoperm](https://docs.rs/autoperm)
+//! at compile time.
+//!
+//! Stack effects are parsed while the macro expands, so a malformed diagram is a compile error.
+//! Pointing at the offending symbol inside the string literal needs `Literal::subspan`, which
+//! returns `None` on stable compilers, so there the error points at the whole literal.
+
+use std::ops::Range;
+
+use autoperm::{models::Rust, ParseError};
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{quote, ToTokens};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input, Expr, LitStr, Token,
+};
+
+/// The arguments of [`permute!`]
+struct Permute {
+    stack: Expr,
+    effect: LitStr,
+}
+
+impl Parse for Permute {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let stack = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let effect = input.parse()?;
+        input.parse::<Option<Token![,]>>()?;
+        Ok(Permute { stack, effect })
+    }
+}
+
+/// Applies a stack effect diagram to a `Vec` whose last item is the top of the stack.
+///
+/// The first argument is a place holding the `Vec`, so a `&mut Vec<T>` named `v` is written `*v`.
+/// The items must implement `Clone` when the diagram duplicates them. The code is generated by
+/// [`Rust`](autoperm::models::Rust).
+///
+/// # Example
+///
+/// ```
+/// use autoperm_macros::permute;
+///
+/// let mut stack = vec![1, 2, 3, 4];
+///
+/// permute!(stack, "a b c -- c a b");
+/// assert_eq!(stack, vec![1, 4, 2, 3]);
+///
+/// permute!(stack, "a b -- b a b");
+/// assert_eq!(stack, vec![1, 4, 3, 2, 3]);
+/// ```
+///
+/// A diagram that doesn't parse is a compile error:
+///
+/// ```compile_fail
+/// use autoperm_macros::permute;
+///
+/// let mut stack = vec![1, 2];
+///
+/// permute!(stack, "a b -- a c");
+/// ```
+#[proc_macro]
+pub fn permute(input: TokenStream) -> TokenStream {
+    let Permute { stack, effect } = parse_macro_input!(input as Permute);
+
+    let code = match autoperm::autoperm(&effect.value(), Rust::vec("stack")) {
+        Ok(code) => code,
+        Err(err) => return error(&effect, &err).into(),
+    };
+    let code: proc_macro2::TokenStream = code.parse().expect("generated invalid Rust");
+
+    quote! {
+        {
+            let stack = &mut #stack;
+            #code
+        }
+    }
+    .into()
+}
+
+/// Generates the brainfuck program for a stack effect diagram as a `&'static str` literal.
+///
+/// This is [`autoperm_bf`](autoperm::autoperm_bf) run at compile time.
+///
+/// # Example
+///
+/// ```
+/// use autoperm_macros::autoperm_bf;
+///
+/// const SWAP: &str = autoperm_bf!("a b -- b a");
+///
+/// assert_eq!(SWAP, "[->+<]<[->+<]>>[-<<+>>]<");
+/// ```
+///
+/// A diagram that doesn't parse is a compile error:
+///
+/// ```compile_fail
+/// use autoperm_macros::autoperm_bf;
+///
+/// const BROKEN: &str = autoperm_bf!("a b -- a c");
+/// ```
+#[proc_macro]
+pub fn autoperm_bf(input: TokenStream) -> TokenStream {
+    let effect = parse_macro_input!(input as LitStr);
+
+    match autoperm::autoperm_bf(&effect.value()) {
+        Ok(program) => LitStr::new(&program, effect.span())
+            .into_token_stream()
+            .into(),
+        Err(err) => error(&effect, &err).into(),
+    }
+}
+
+/// Turns a [`ParseError`] into a `compile_error!`, pointing at the part of the literal the error
+/// is about when [`subspan`] can find it and at the whole literal otherwise
+fn error(literal: &LitStr, err: &ParseError) -> proc_macro2::TokenStream {
+    let span = err
+        .span(&literal.value())
+        .and_then(|range| subspan(literal, range))
+        .unwrap_or_else(|| literal.span());
+
+    syn::Error::new(span, err).to_compile_error()
+}
+
+/// Returns the span of a range of bytes in the value of a string literal, if the compiler can
+/// point inside literals. `Literal::subspan` always returns `None` on stable.
+fn subspan(literal: &LitStr, range: Range<usize>) -> Option<Span> {
+    let token = literal.token();
+    let text = token.to_string();
+    let value = literal.value();
+
+    // Escapes move the value around inside the literal
+    let offset = text.find('"')? + 1;
+    if text.get(offset..offset + value.len())? != value {
+        return None;
+    }
+
+    token.subspan(offset + range.start..offset + range.end)
+}
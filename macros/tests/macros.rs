@@ -0,0 +1,51 @@
+use autoperm_macros::{autoperm_bf, permute};
+
+#[test]
+fn permute() {
+    let mut stack = vec![1, 2, 3, 4, 5, 6];
+
+    permute!(stack, "a b -- b a");
+    assert_eq!(stack, [1, 2, 3, 4, 6, 5]);
+
+    permute!(stack, "a b c d e f -- c d d f e e b");
+    assert_eq!(stack, [3, 4, 4, 5, 6, 6, 2]);
+
+    permute!(stack, "a b c --");
+    assert_eq!(stack, [3, 4, 4, 5]);
+
+    permute!(stack, "a -- a a a a");
+    assert_eq!(stack, [3, 4, 4, 5, 5, 5, 5]);
+
+    permute!(stack, "a b c d e f g -- g f e d c b a");
+    assert_eq!(stack, [5, 5, 5, 5, 4, 4, 3]);
+
+    permute!(stack, "--");
+    assert_eq!(stack, [5, 5, 5, 5, 4, 4, 3]);
+}
+
+#[test]
+fn values_that_are_only_cloned() {
+    let mut stack: Vec<String> = ["a", "b", "c"].map(String::from).to_vec();
+
+    // `a` is dropped from its place and only copied to the new positions
+    permute!(stack, "a b c -- b c c a a");
+    assert_eq!(stack, ["b", "c", "c", "a", "a"]);
+
+    // Through a mutable reference
+    let reference = &mut stack;
+    permute!(*reference, "a b -- b a");
+    permute!(*reference, "a b c d e -- e");
+    assert_eq!(stack, ["a"]);
+}
+
+#[test]
+fn brainfuck() {
+    const DUP: &str = autoperm_bf!("a -- a a");
+    assert_eq!(DUP, autoperm::autoperm_bf("a -- a a").unwrap());
+
+    assert_eq!(autoperm_bf!("a b -- a b"), "");
+    assert_eq!(
+        autoperm_bf!(r"a b c -- c"),
+        autoperm::autoperm_bf("a b c -- c").unwrap()
+    );
+}
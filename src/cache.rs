@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use itertools::Itertools;
+
+use crate::{solve_with, Instruction, Model, SolveOptions, StackEffectDiagram};
+
+/// Remembers the solutions of diagrams so each one is only solved once.
+///
+/// Diagrams are compared after parsing, so `a b -- b a` and `x y -- y x` share a solution. The
+/// cache can be saved to and loaded from a simple line based text format to reuse solutions
+/// between runs.
+///
+/// # Example
+///
+/// ```
+/// use autoperm::{parse, SolutionCache, SolveOptions};
+/// use autoperm::models::Brainfuck;
+///
+/// let mut cache = SolutionCache::new();
+/// let options = SolveOptions::new();
+///
+/// let swap = cache.generate(&parse("a b -- b a").unwrap(), &options, Brainfuck::new());
+/// let same = cache.generate(&parse("x y -- y x").unwrap(), &options, Brainfuck::new());
+///
+/// assert_eq!(swap, same);
+/// assert_eq!(cache.len(), 1);
+///
+/// // Save the cache and load it again
+/// let mut file = Vec::new();
+/// cache.save(&mut file).unwrap();
+///
+/// assert_eq!(SolutionCache::load(&file[..]).unwrap(), cache);
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct SolutionCache {
+    solutions: HashMap<(StackEffectDiagram, SolveOptions), Vec<Instruction>>,
+}
+
+impl SolutionCache {
+    /// Creates an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the instructions that apply a diagram, solving it with
+    /// [`solve_with`](crate::solve_with) if it isn't in the cache yet
+    pub fn solve(
+        &mut self,
+        diagram: &StackEffectDiagram,
+        options: &SolveOptions,
+    ) -> &[Instruction] {
+        self.solutions
+            .entry((diagram.clone(), options.clone()))
+            .or_insert_with(|| solve_with(diagram, options))
+    }
+
+    /// Generates a program from the cached instructions of a diagram using a given [`Model`](crate::Model).
+    pub fn generate<M>(
+        &mut self,
+        diagram: &StackEffectDiagram,
+        options: &SolveOptions,
+        model: M,
+    ) -> M::Output
+    where
+        M: Model,
+    {
+        crate::generate(self.solve(diagram, options).iter().cloned(), model)
+    }
+
+    /// The number of cached solutions
+    pub fn len(&self) -> usize {
+        self.solutions.len()
+    }
+
+    /// Returns true if nothing has been cached
+    pub fn is_empty(&self) -> bool {
+        self.solutions.is_empty()
+    }
+
+    /// Writes every solution, one per line, in the format read by [`load`](SolutionCache::load).
+    ///
+    /// The lines are sorted so saving the same cache always writes the same file.
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let lines = self
+            .solutions
+            .iter()
+            .map(|((diagram, options), instructions)| {
+                format!(
+                    "{}:{}\t{}\t{}",
+                    diagram.inputs,
+                    diagram.mapping.iter().join(","),
+                    options.encode(),
+                    instructions.iter().map(encode).join(" ")
+                )
+            })
+            .sorted();
+
+        for line in lines {
+            writeln!(writer, "{}", line)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads solutions written by [`save`](SolutionCache::save).
+    ///
+    /// Returns an error of kind [`InvalidData`](std::io::ErrorKind::InvalidData) if a line can't be read.
+    pub fn load<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut cache = SolutionCache::new();
+
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid solution on line {}", number + 1),
+                )
+            };
+
+            let (diagram, options, instructions) =
+                line.split('\t').collect_tuple().ok_or_else(invalid)?;
+
+            let (inputs, mapping) = diagram.split_once(':').ok_or_else(invalid)?;
+            let diagram = StackEffectDiagram {
+                inputs: inputs.parse().map_err(|_| invalid())?,
+                mapping: list(mapping).ok_or_else(invalid)?,
+            };
+            if diagram.mapping.iter().any(|&i| i >= diagram.inputs) {
+                return Err(invalid());
+            }
+            let options = SolveOptions::decode(options).ok_or_else(invalid)?;
+            let instructions = instructions
+                .split(' ')
+                .filter(|instruction| !instruction.is_empty())
+                .map(decode)
+                .collect::<Option<_>>()
+                .ok_or_else(invalid)?;
+
+            cache.solutions.insert((diagram, options), instructions);
+        }
+
+        Ok(cache)
+    }
+}
+
+/// Writes an instruction as a letter followed by its cells, such as `M1>2,0`
+fn encode(instruction: &Instruction) -> String {
+    match instruction {
+        Instruction::Clear { cell } => format!("C{}", cell),
+        Instruction::Mov { cell, to } => format!("M{}>{}", cell, to.iter().join(",")),
+        Instruction::WeightedMov { cell, to } => format!(
+            "W{}>{}",
+            cell,
+            to.iter()
+                .map(|(target, weight)| format!("{}*{}", target, weight))
+                .join(",")
+        ),
+        Instruction::Start { cell } => format!("S{}", cell),
+        Instruction::Top { cell } => format!("T{}", cell),
+    }
+}
+
+fn decode(s: &str) -> Option<Instruction> {
+    let (kind, rest) = s.split_at_checked(1)?;
+
+    match kind {
+        "C" => Some(Instruction::Clear {
+            cell: rest.parse().ok()?,
+        }),
+        "S" => Some(Instruction::Start {
+            cell: rest.parse().ok()?,
+        }),
+        "T" => Some(Instruction::Top {
+            cell: rest.parse().ok()?,
+        }),
+        "M" => {
+            let (cell, to) = rest.split_once('>')?;
+            Some(Instruction::Mov {
+                cell: cell.parse().ok()?,
+                to: list(to)?,
+            })
+        }
+        "W" => {
+            let (cell, to) = rest.split_once('>')?;
+            let to = to
+                .split(',')
+                .filter(|term| !term.is_empty())
+                .map(|term| {
+                    let (target, weight) = term.split_once('*')?;
+                    Some((target.parse().ok()?, weight.parse().ok()?))
+                })
+                .collect::<Option<_>>()?;
+            Some(Instruction::WeightedMov {
+                cell: cell.parse().ok()?,
+                to,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Reads a comma separated list of numbers
+fn list<T: std::str::FromStr>(s: &str) -> Option<Vec<T>> {
+    s.split(',')
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse().ok())
+        .collect()
+}
@@ -0,0 +1,72 @@
+use crate::parse::StackEffectDiagram;
+
+impl StackEffectDiagram {
+    /// Returns the diagram that applies `self` followed by `next`.
+    ///
+    /// If `next` consumes more items than `self` produces the extra items are taken from below
+    /// the inputs of `self`, so the resulting diagram may have more inputs than either part.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use autoperm::parse;
+    ///
+    /// let swap = parse("a b -- b a").unwrap();
+    /// let over = parse("a b -- a b a").unwrap();
+    ///
+    /// assert_eq!(swap.then(&over), parse("a b -- b a b").unwrap());
+    /// ```
+    pub fn then(&self, next: &StackEffectDiagram) -> StackEffectDiagram {
+        // Items `next` needs that `self` doesn't provide come from deeper in the stack
+        let extra = next.inputs.saturating_sub(self.mapping.len());
+
+        // The stack between the two diagrams, in terms of the combined inputs
+        let middle: Vec<usize> = (0..extra)
+            .chain(self.mapping.iter().map(|i| i + extra))
+            .collect();
+
+        let split = middle.len() - next.inputs;
+
+        let mapping = middle[..split]
+            .iter()
+            .copied()
+            .chain(next.mapping.iter().map(|i| middle[split + i]))
+            .collect();
+
+        StackEffectDiagram {
+            mapping,
+            inputs: self.inputs + extra,
+        }
+    }
+}
+
+/// Compose a sequence of [`StackEffectDiagram`](crate::StackEffectDiagram)s, applied one after
+/// another, into a single diagram.
+///
+/// Solving the composed diagram produces one program without the intermediate pointer shifts
+/// that come from generating each diagram separately. An empty sequence composes to the
+/// identity `( -- )`.
+///
+/// # Example
+///
+/// ```
+/// use autoperm::{compose, parse};
+///
+/// // SWAP OVER ROT
+/// let diagrams = [
+///     parse("a b -- b a").unwrap(),
+///     parse("a b -- a b a").unwrap(),
+///     parse("a b c -- b c a").unwrap(),
+/// ];
+///
+/// assert_eq!(compose(&diagrams), parse("a b -- a b b").unwrap());
+/// ```
+pub fn compose(diagrams: &[StackEffectDiagram]) -> StackEffectDiagram {
+    diagrams.iter().fold(
+        StackEffectDiagram {
+            mapping: vec![],
+            inputs: 0,
+        },
+        |acc, diagram| acc.then(diagram),
+    )
+}
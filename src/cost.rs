@@ -0,0 +1,100 @@
+use crate::{Instruction, Layout};
+
+/// A model agnostic report of how expensive a list of instructions is.
+///
+/// # Example
+///
+/// ```
+/// use autoperm::{parse, solve, Cost};
+///
+/// let instructions = solve(&parse("a b -- b a").unwrap());
+///
+/// assert_eq!(Cost::new(&instructions), Cost {
+///     movs: 3,
+///     clears: 0,
+///     temp_uses: 1,
+///     travel: 12,
+/// });
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Cost {
+    /// The number of `Mov` and `WeightedMov` instructions
+    pub movs: usize,
+    /// The number of `Clear` instructions
+    pub clears: usize,
+    /// The number of times data is parked in the temporary cell
+    pub temp_uses: usize,
+    /// The total distance the pointer travels, when it visits the source and then each target
+    /// of every move before returning to the source
+    pub travel: usize,
+}
+
+impl Cost {
+    /// Computes the cost of a list of instructions solved with the default [`Layout`].
+    ///
+    /// The temporary cell is the first cell above both the starting and the final top of the
+    /// stack, so `temp_uses` is only counted when the list has a `Start` and a `Top`.
+    pub fn new(instructions: &[Instruction]) -> Self {
+        Self::with_layout(instructions, &Layout::upward())
+    }
+
+    /// Computes the cost of a list of instructions solved with a [`Layout`], which decides where
+    /// the temporary cell is.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use autoperm::{parse, solve_with, Cost, Layout, SolveOptions};
+    ///
+    /// let layout = Layout::downward();
+    /// let options = SolveOptions::new().layout(layout);
+    /// let instructions = solve_with(&parse("a b -- b a").unwrap(), &options);
+    ///
+    /// assert_eq!(Cost::with_layout(&instructions, &layout).temp_uses, 1);
+    /// ```
+    pub fn with_layout(instructions: &[Instruction], layout: &Layout) -> Self {
+        let temp = layout
+            .scratch_of(instructions)
+            .map(|scratch| scratch.cell(0));
+
+        let mut cost = Cost::default();
+        let mut ptr = None;
+
+        for instruction in instructions {
+            match instruction {
+                Instruction::Clear { cell } => {
+                    cost.clears += 1;
+                    cost.visit(&mut ptr, *cell);
+                }
+                Instruction::Mov { cell, to } => {
+                    cost.movs += 1;
+                    if temp.is_some_and(|temp| to[..] == [temp]) {
+                        cost.temp_uses += 1;
+                    }
+                    cost.visit(&mut ptr, *cell);
+                    to.iter().for_each(|target| cost.visit(&mut ptr, *target));
+                    cost.visit(&mut ptr, *cell);
+                }
+                Instruction::WeightedMov { cell, to } => {
+                    cost.movs += 1;
+                    cost.visit(&mut ptr, *cell);
+                    to.iter()
+                        .for_each(|(target, _)| cost.visit(&mut ptr, *target));
+                    cost.visit(&mut ptr, *cell);
+                }
+                Instruction::Start { cell } => ptr = Some(*cell),
+                Instruction::Top { cell } => cost.visit(&mut ptr, *cell),
+            }
+        }
+
+        cost
+    }
+
+    /// Moves the pointer to a cell. Without a `Start` the first cell visited is free.
+    fn visit(&mut self, ptr: &mut Option<isize>, cell: isize) {
+        if let Some(ptr) = ptr {
+            self.travel += (cell - *ptr).unsigned_abs();
+        }
+        *ptr = Some(cell);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{
+    graph::{Component, MoveGraph, Walk},
+    solve, Instruction, StackEffectDiagram,
+};
+
+/// Draws the graph of moves [`solve`](crate::solve) builds for a [`StackEffectDiagram`](crate::StackEffectDiagram)
+/// in the [Graphviz](https://graphviz.org) DOT language.
+///
+/// There is a node for every position on the stack and an edge from every position to each
+/// position its value is copied to. Every cycle of two or more positions is drawn inside a
+/// dashed cluster, and the edges that form a cycle, including self loops, are bold.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::{parse, to_dot};
+///
+/// let dot = to_dot(&parse("a b c -- b a a").unwrap());
+///
+/// assert_eq!(dot, "\
+/// digraph {
+///     node [shape = circle]
+///     subgraph cluster_0 {
+///         label = \"cycle\"
+///         style = dashed
+///         0
+///         1
+///     }
+///     2
+///     0 -> 2
+///     0 -> 1 [style = bold]
+///     1 -> 0 [style = bold]
+/// }
+/// ");
+/// ```
+pub fn to_dot(diagram: &StackEffectDiagram) -> String {
+    dot(&MoveGraph::from_diagram(diagram), None)
+}
+
+/// Draws the same graph as [`to_dot`], with every node numbered by when its value is first moved
+/// or cleared by the instructions [`solve`](crate::solve) emits.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::{parse, to_dot_ordered};
+///
+/// let dot = to_dot_ordered(&parse("a b -- b").unwrap());
+///
+/// assert_eq!(dot, "\
+/// digraph {
+///     node [shape = circle]
+///     0 [xlabel = \"1\"]
+///     1 [xlabel = \"2\"]
+///     1 -> 0
+/// }
+/// ");
+/// ```
+pub fn to_dot_ordered(diagram: &StackEffectDiagram) -> String {
+    let graph = MoveGraph::from_diagram(diagram);
+
+    // Instructions that use the temporary cell count as steps too
+    let mut order = vec![None; graph.len()];
+    let steps = solve(diagram).into_iter().filter_map(|i| match i {
+        Instruction::Clear { cell } | Instruction::Mov { cell, .. } => Some(cell as usize),
+        _ => None,
+    });
+    for (step, cell) in steps.enumerate() {
+        if let Some(order) = order.get_mut(cell) {
+            order.get_or_insert(step + 1);
+        }
+    }
+
+    dot(&graph, Some(&order))
+}
+
+fn dot(graph: &MoveGraph, order: Option<&[Option<usize>]>) -> String {
+    let node = |node: usize| match order.and_then(|order| order[node]) {
+        Some(step) => format!("{} [xlabel = \"{}\"]", node, step),
+        None => node.to_string(),
+    };
+
+    let mut dot = "digraph {\n    node [shape = circle]\n".to_string();
+
+    // Following the sources from the root of a cycle visits the whole cycle
+    let mut walk = Walk::new(graph);
+    let mut cycles = 0;
+    while let Some(component) = walk.next(graph) {
+        if let Component::Cycle(root) = component {
+            let mut cycle = vec![root];
+            let mut next = graph.source(root).unwrap();
+            while next != root {
+                cycle.push(next);
+                next = graph.source(next).unwrap();
+            }
+            cycle.sort();
+
+            dot += &format!(
+                "    subgraph cluster_{} {{\n        label = \"cycle\"\n        style = dashed\n",
+                cycles
+            );
+            for &n in &cycle {
+                dot += &format!("        {}\n", node(n));
+            }
+            dot += "    }\n";
+            cycles += 1;
+        }
+    }
+
+    // A self loop is on a cycle but not in a cluster
+    for n in 0..graph.len() {
+        if !graph.on_cycle(n) || graph.source(n) == Some(n) {
+            dot += &format!("    {}\n", node(n));
+        }
+    }
+
+    for n in 0..graph.len() {
+        for &target in graph.targets(n) {
+            if graph.on_cycle(target) {
+                dot += &format!("    {} -> {} [style = bold]\n", n, target);
+            } else {
+                dot += &format!("    {} -> {}\n", n, target);
+            }
+        }
+    }
+
+    dot + "}\n"
+}
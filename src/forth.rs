@@ -0,0 +1,130 @@
+use crate::{parse, search::cheapest, Primitive, SearchError, StackEffectDiagram};
+
+/// The standard Forth words that can be used on a stack of `len` items
+fn words(len: usize) -> Vec<Primitive> {
+    let mut words: Vec<Primitive> = [
+        ("SWAP", "a b -- b a"),
+        ("DUP", "a -- a a"),
+        ("OVER", "a b -- a b a"),
+        ("ROT", "a b c -- b c a"),
+        ("DROP", "a --"),
+        ("NIP", "a b -- b"),
+        ("TUCK", "a b -- b a b"),
+    ]
+    .into_iter()
+    .map(|(name, effect)| Primitive::new(name, parse(effect).unwrap(), 1))
+    .collect();
+
+    // Shallower PICKs and ROLLs have names, and the depth is a word of its own
+    words.extend((2..len).map(pick));
+    words.extend((3..len).map(roll));
+    words
+}
+
+/// `n PICK` copies the item `n` below the top
+fn pick(n: usize) -> Primitive {
+    let mut mapping: Vec<usize> = (0..=n).collect();
+    mapping.push(0);
+    let diagram = StackEffectDiagram {
+        inputs: n + 1,
+        mapping,
+    };
+    Primitive::new(format!("{} PICK", n), diagram, 2)
+}
+
+/// `n ROLL` moves the item `n` below the top to the top
+fn roll(n: usize) -> Primitive {
+    let mut mapping: Vec<usize> = (1..=n).collect();
+    mapping.push(0);
+    let diagram = StackEffectDiagram {
+        inputs: n + 1,
+        mapping,
+    };
+    Primitive::new(format!("{} ROLL", n), diagram, 2)
+}
+
+/// A sequence of Forth words returned by [`forth`](crate::forth)
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct ForthWords {
+    /// The words in the order they run, with `n PICK` and `n ROLL` as one item each
+    pub words: Vec<String>,
+    /// Whether no shorter sequence exists. It is `false` when the diagram was too large to
+    /// search and the words are a longer sequence that still applies it.
+    pub shortest: bool,
+}
+
+/// Generate the shortest sequence of standard Forth words that applies a [`StackEffectDiagram`](crate::StackEffectDiagram).
+///
+/// The words are `SWAP DUP OVER ROT DROP NIP TUCK PICK ROLL`, where `n PICK` and `n ROLL` count as
+/// two words, and the sequence never reaches below the inputs of the diagram.
+///
+/// When a diagram is too large to [`search`](crate::search), the words pick every output and
+/// then roll away the inputs instead, and [`shortest`](ForthWords::shortest) is `false`.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::{forth, parse};
+///
+/// assert_eq!(forth(&parse("a b -- b a").unwrap()).words, ["SWAP"]);
+/// assert_eq!(forth(&parse("a b c -- c a b").unwrap()).words, ["ROT", "ROT"]);
+/// assert_eq!(forth(&parse("a b c d -- b c d a").unwrap()).words, ["3 ROLL"]);
+/// assert!(forth(&parse("a b -- a b").unwrap()).words.is_empty());
+///
+/// let reverse = forth(&parse("a b c d e f g h i j -- j i h g f e d c b a").unwrap());
+/// assert!(!reverse.shortest);
+/// assert_eq!(reverse.words.len(), 30);
+/// ```
+pub fn forth(diagram: &StackEffectDiagram) -> ForthWords {
+    let fallback = construct(diagram);
+
+    // The shortest sequence is never longer than the fallback
+    let words = words(std::cmp::max(diagram.inputs, diagram.mapping.len()) + 2);
+    let tokens = fallback.iter().map(|word| word.split(' ').count()).sum();
+
+    match cheapest(&words, diagram, fallback.len(), tokens) {
+        Ok(sequence) => ForthWords {
+            words: sequence
+                .into_iter()
+                .map(|i| words[i].name.clone())
+                .collect(),
+            shortest: true,
+        },
+        Err(SearchError::TooLarge) => ForthWords {
+            words: fallback,
+            shortest: false,
+        },
+        Err(SearchError::NotExpressible { .. }) => {
+            unreachable!("the fallback is a sequence of the words within the limits")
+        }
+    }
+}
+
+/// Picks a copy of every output onto the stack and then rolls each input off
+fn construct(diagram: &StackEffectDiagram) -> Vec<String> {
+    let pick = |depth| match depth {
+        0 => "DUP".to_string(),
+        1 => "OVER".to_string(),
+        depth => pick(depth).name,
+    };
+
+    let mut words: Vec<String> = diagram
+        .mapping
+        .iter()
+        .enumerate()
+        .map(|(picked, &i)| pick(diagram.inputs - 1 - i + picked))
+        .collect();
+
+    // The top input is just below the outputs
+    let outputs = diagram.mapping.len();
+    for _ in 0..diagram.inputs {
+        match outputs {
+            0 => words.push("DROP".to_string()),
+            1 => words.push("NIP".to_string()),
+            2 => words.extend(["ROT".to_string(), "DROP".to_string()]),
+            depth => words.extend([roll(depth).name, "DROP".to_string()]),
+        }
+    }
+
+    words
+}
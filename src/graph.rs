@@ -0,0 +1,284 @@
+use crate::StackEffectDiagram;
+
+/// The graph of moves between cells, with an edge from every cell to each cell its data is
+/// copied to.
+///
+/// Every node has at most one source, so the graph is a functional graph. Each weakly connected
+/// component holds at most one cycle and the cycle is the root of the trees hanging off of it.
+/// That lets the graph be walked in linear time without a general purpose strongly connected
+/// components algorithm.
+pub(crate) struct MoveGraph {
+    /// The node each node gets its data from
+    sources: Vec<Option<usize>>,
+    /// The targets of node `i` are `targets[offsets[i]..offsets[i + 1]]`, highest first
+    offsets: Vec<usize>,
+    targets: Vec<usize>,
+    on_cycle: Vec<bool>,
+    uses_temp: bool,
+}
+
+/// A strongly connected component of a [`MoveGraph`]
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Component {
+    /// A node that is not part of a cycle, or is only part of its own self loop
+    Node(usize),
+    /// A cycle, given by the node the search entered it from. Following the sources from the
+    /// root visits every node of the cycle and ends back at the root.
+    Cycle(usize),
+}
+
+impl MoveGraph {
+    /// Creates a graph where `sources[i]` is the node whose data ends up in node `i`
+    pub(crate) fn new(sources: Vec<Option<usize>>) -> Self {
+        let len = sources.len();
+
+        // Count the targets of each node and lay them out highest first
+        let mut offsets = vec![0; len + 1];
+        for source in sources.iter().flatten() {
+            offsets[*source + 1] += 1;
+        }
+        for i in 0..len {
+            offsets[i + 1] += offsets[i];
+        }
+
+        let mut cursor = offsets.clone();
+        let mut targets = vec![0; offsets[len]];
+        for (node, source) in sources.iter().enumerate().rev() {
+            if let Some(source) = source {
+                targets[cursor[*source]] = node;
+                cursor[*source] += 1;
+            }
+        }
+
+        let mut graph = MoveGraph {
+            sources,
+            offsets,
+            targets,
+            on_cycle: vec![false; len],
+            uses_temp: false,
+        };
+        graph.find_cycles();
+        graph
+    }
+
+    /// Follows the sources from every node to mark the nodes that are part of a cycle
+    fn find_cycles(&mut self) {
+        const NEW: u8 = 0;
+        const WALKING: u8 = 1;
+        const DONE: u8 = 2;
+
+        let mut state = vec![NEW; self.len()];
+        let mut path = Vec::new();
+
+        for start in 0..self.len() {
+            let mut node = Some(start);
+            while let Some(n) = node {
+                if state[n] != NEW {
+                    break;
+                }
+                state[n] = WALKING;
+                path.push(n);
+                node = self.sources[n];
+            }
+
+            // Reaching a node on the current walk closes a cycle
+            if let Some(n) = node {
+                if state[n] == WALKING {
+                    let cycle = &path[path.iter().position(|&p| p == n).unwrap()..];
+                    cycle.iter().for_each(|&c| self.on_cycle[c] = true);
+
+                    if cycle.len() > 1 || self.targets(n).len() > 1 {
+                        self.uses_temp = true;
+                    }
+                }
+            }
+
+            path.drain(..).for_each(|p| state[p] = DONE);
+        }
+    }
+
+    /// Creates the graph of a stack effect diagram, with a node for every position the inputs or
+    /// outputs use
+    pub(crate) fn from_diagram(diagram: &StackEffectDiagram) -> Self {
+        let nodes = std::cmp::max(diagram.inputs, diagram.mapping.len());
+        Self::new(
+            (0..nodes)
+                .map(|i| diagram.mapping.get(i).copied())
+                .collect(),
+        )
+    }
+
+    /// The number of nodes in the graph
+    pub(crate) fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// The node a node's data comes from
+    pub(crate) fn source(&self, node: usize) -> Option<usize> {
+        self.sources[node]
+    }
+
+    /// The nodes a node's data is copied to, highest first
+    pub(crate) fn targets(&self, node: usize) -> &[usize] {
+        &self.targets[self.offsets[node]..self.offsets[node + 1]]
+    }
+
+    /// True if a node is part of a cycle, including a self loop
+    pub(crate) fn on_cycle(&self, node: usize) -> bool {
+        self.on_cycle[node]
+    }
+
+    /// True if breaking the cycles of the graph needs a temporary cell
+    pub(crate) fn uses_temp(&self) -> bool {
+        self.uses_temp
+    }
+}
+
+/// The state of a depth first search over a [`MoveGraph`] that finds its components one at a
+/// time. It doesn't borrow the graph so that it can be stored next to it.
+pub(crate) struct Walk {
+    visited: Vec<bool>,
+    /// The depth first search stack of nodes and the index of their next target
+    stack: Vec<(usize, usize)>,
+    next_root: usize,
+}
+
+impl Walk {
+    pub(crate) fn new(graph: &MoveGraph) -> Self {
+        Walk {
+            visited: vec![false; graph.len()],
+            stack: Vec::new(),
+            next_root: 0,
+        }
+    }
+
+    /// Finds the next strongly connected component. Components are found in reverse topological
+    /// order, so a component only comes after every component it moves data to.
+    ///
+    /// The order is the same as petgraph's `tarjan_scc` gives for the equivalent graph.
+    pub(crate) fn next(&mut self, graph: &MoveGraph) -> Option<Component> {
+        loop {
+            if let Some((node, next)) = self.stack.last_mut() {
+                let node = *node;
+                let targets = graph.targets(node);
+
+                if let Some(&target) = targets.get(*next) {
+                    *next += 1;
+                    if !self.visited[target] {
+                        self.visited[target] = true;
+                        self.stack.push((target, 0));
+                    }
+                    continue;
+                }
+
+                self.stack.pop();
+
+                if !graph.on_cycle[node] {
+                    return Some(Component::Node(node));
+                }
+
+                // A cycle can only be entered from itself, so it is finished when the search
+                // that started on it is
+                if self.stack.is_empty() {
+                    if graph.sources[node] == Some(node) {
+                        return Some(Component::Node(node));
+                    }
+
+                    return Some(Component::Cycle(node));
+                }
+            } else {
+                while self.next_root < graph.len() && self.visited[self.next_root] {
+                    self.next_root += 1;
+                }
+
+                if self.next_root == graph.len() {
+                    return None;
+                }
+
+                self.visited[self.next_root] = true;
+                self.stack.push((self.next_root, 0));
+            }
+        }
+    }
+}
+
+/// Finds the strongly connected components of any graph, given the nodes to search from and the
+/// targets of each node. Nodes that are not reachable from `nodes` are left out.
+///
+/// This is the algorithm by Pearce that petgraph's `tarjan_scc` uses, written without recursion.
+/// Components come in reverse topological order, and the nodes of a component in the order the
+/// search finished them, with the node the component was entered from last.
+pub(crate) fn strongly_connected<N, T, I>(len: usize, nodes: N, mut targets: T) -> Vec<Vec<usize>>
+where
+    N: IntoIterator<Item = usize>,
+    T: FnMut(usize) -> I,
+    I: Iterator<Item = usize>,
+{
+    // 0 is unvisited, finished components count down from the top
+    let mut rindex = vec![0; len];
+    let mut index = 1;
+    let mut component = usize::MAX;
+
+    let mut components = Vec::new();
+    let mut stack = Vec::new();
+    // The depth first search stack of nodes, their remaining targets and whether they are a root
+    let mut frames: Vec<(usize, I, bool)> = Vec::new();
+
+    for start in nodes {
+        if rindex[start] != 0 {
+            continue;
+        }
+        rindex[start] = index;
+        index += 1;
+        frames.push((start, targets(start), true));
+
+        while let Some((node, next, root)) = frames.last_mut() {
+            let node = *node;
+            if let Some(target) = next.next() {
+                if rindex[target] == 0 {
+                    rindex[target] = index;
+                    index += 1;
+                    frames.push((target, targets(target), true));
+                } else if rindex[target] < rindex[node] {
+                    rindex[node] = rindex[target];
+                    *root = false;
+                }
+                continue;
+            }
+
+            let root = *root;
+            frames.pop();
+            if root {
+                let mut adjustment = 1;
+                let start = stack
+                    .iter()
+                    .rposition(|&n| {
+                        if rindex[node] > rindex[n] {
+                            true
+                        } else {
+                            rindex[n] = component;
+                            adjustment += 1;
+                            false
+                        }
+                    })
+                    .map_or(0, |p| p + 1);
+                rindex[node] = component;
+                stack.push(node);
+                components.push(stack.split_off(start));
+                index -= adjustment;
+                component -= 1;
+            } else {
+                stack.push(node);
+            }
+
+            if let Some((parent, _, parent_root)) = frames.last_mut() {
+                if rindex[node] < rindex[*parent] {
+                    rindex[*parent] = rindex[node];
+                    *parent_root = false;
+                }
+            }
+        }
+    }
+
+    components
+}
@@ -0,0 +1,217 @@
+use crate::Instruction;
+
+/// Describes where the items of a stack are stored on the tape.
+///
+/// Positions count up from the deepest input of a diagram, which is stored in the `base` cell.
+/// The default layout grows the stack toward higher cells from cell 0, so position `i` is stored
+/// in cell `i`. In general position `i` is stored in cell `base + i * stride`, where the stride is
+/// negative for stacks that grow toward lower cells.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::{generate, parse, solve_with, Layout, SolveOptions};
+/// use autoperm::models::Brainfuck;
+///
+/// let diagram = parse("a b -- b a").unwrap();
+///
+/// // The top of the stack is the lowest cell
+/// let options = SolveOptions::new().layout(Layout::downward());
+/// let program = generate(solve_with(&diagram, &options), Brainfuck::new());
+///
+/// assert_eq!(program, "[-<+>]>[-<+>]<<[->>+<<]>");
+/// ```
+///
+/// Every item is followed by a flag, and the flag after the top item is free to use as the
+/// temporary cell:
+///
+/// ```
+/// use autoperm::{generate, parse, solve_with, Layout, SolveOptions};
+/// use autoperm::models::Brainfuck;
+///
+/// let diagram = parse("a b -- b a").unwrap();
+///
+/// let layout = Layout::upward().stride(2).temp_gap(1);
+/// let options = SolveOptions::new().layout(layout).assume_zero(3);
+/// let program = generate(solve_with(&diagram, &options), Brainfuck::new());
+///
+/// assert_eq!(program, "[->+<]<<[->>+<<]>>>[-<<<+>>>]<");
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Layout {
+    base: isize,
+    step: isize,
+    gap: Option<isize>,
+}
+
+impl Layout {
+    /// A stack that grows toward higher cells
+    pub fn upward() -> Self {
+        Self {
+            base: 0,
+            step: 1,
+            gap: None,
+        }
+    }
+
+    /// A stack that grows toward lower cells, position `i` is stored in cell `-i`
+    pub fn downward() -> Self {
+        Self {
+            step: -1,
+            ..Self::upward()
+        }
+    }
+
+    /// Sets the cell that stores the deepest input
+    pub fn base(mut self, base: isize) -> Self {
+        self.base = base;
+        self
+    }
+
+    /// Stores an item every `stride` cells, leaving the cells in between alone
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stride` is 0, or if it leaves no room for a [`temp_gap`](Layout::temp_gap)
+    /// that is already set
+    pub fn stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0, "the stride of a layout must be positive");
+        self.step = self.step.signum() * stride as isize;
+        self.validate();
+        self
+    }
+
+    /// Uses the cell `offset` cells past the highest item of the stack as the temporary cell,
+    /// instead of the next free position of the stack.
+    ///
+    /// The highest item is the top of the inputs or the outputs, whichever is higher. Nothing is
+    /// assumed about a gap cell, so it is cleared before it is used unless it is marked with
+    /// [`assume_zero`](crate::SolveOptions::assume_zero).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` is 0 or not smaller than the [`stride`](Layout::stride), so the stride
+    /// has to be set first
+    pub fn temp_gap(mut self, offset: usize) -> Self {
+        assert!(offset > 0, "the temporary cell must be in a gap");
+        self.gap = Some(offset as isize);
+        self.validate();
+        self
+    }
+
+    /// Returns true if the temporary cell is between two items of the stack
+    fn is_valid(&self) -> bool {
+        self.gap.is_none_or(|gap| gap < self.step.abs())
+    }
+
+    fn validate(&self) {
+        assert!(
+            self.is_valid(),
+            "the temporary cell must be between two items of the stack"
+        );
+    }
+
+    /// Returns the cell that stores a position of the stack
+    pub fn cell(&self, position: isize) -> isize {
+        self.base + position * self.step
+    }
+
+    /// Returns the position of the stack stored in a cell
+    pub(crate) fn position(&self, cell: isize) -> isize {
+        (cell - self.base) / self.step
+    }
+
+    /// Returns the position of the stack stored in a cell, or `None` for the cells between items
+    pub(crate) fn item(&self, cell: isize) -> Option<isize> {
+        ((cell - self.base) % self.step == 0).then(|| self.position(cell))
+    }
+
+    /// Returns the temporary cells of a program that starts with the top of the stack in `start`
+    /// and ends with it in `top`. Without a `top` there is no stack and no known temporary cell.
+    pub(crate) fn scratch(&self, start: Option<isize>, top: Option<isize>) -> Option<Scratch> {
+        let top = top?;
+        let highest = std::cmp::max(self.position(start.unwrap_or(top)), self.position(top));
+        Some(Scratch {
+            first: self.temp(highest + 1).0,
+            direction: self.step.signum(),
+        })
+    }
+
+    /// Returns the temporary cells of a list of instructions, see [`scratch`](Layout::scratch)
+    pub(crate) fn scratch_of(&self, instructions: &[Instruction]) -> Option<Scratch> {
+        let (start, top) = bounds(instructions);
+        self.scratch(start, top)
+    }
+
+    /// Returns the temporary cell of a program whose highest position is `position - 1`, and
+    /// whether it is a gap cell
+    pub(crate) fn temp(&self, position: isize) -> (isize, bool) {
+        match self.gap {
+            Some(offset) => (self.cell(position - 1) + offset * self.step.signum(), true),
+            None => (self.cell(position), false),
+        }
+    }
+
+    /// Writes the layout in the format read by [`decode`](Layout::decode)
+    pub(crate) fn encode(&self) -> String {
+        match self.gap {
+            Some(gap) => format!("{},{},{}", self.base, self.step, gap),
+            None => format!("{},{},-", self.base, self.step),
+        }
+    }
+
+    pub(crate) fn decode(s: &str) -> Option<Self> {
+        let mut fields = s.split(',');
+        let base = fields.next()?.parse().ok()?;
+        let step = fields.next()?.parse().ok()?;
+        let gap = match fields.next()? {
+            "-" => None,
+            gap => Some(gap.parse().ok()?),
+        };
+
+        // The same layouts the builder accepts, so a decoded layout never panics later
+        let layout = Layout { base, step, gap };
+        (fields.next().is_none() && step != 0 && gap.is_none_or(|gap| gap > 0) && layout.is_valid())
+            .then_some(layout)
+    }
+}
+
+/// The temporary cells of a program. The first is the one the [`Layout`] places past both the
+/// starting and the final top of the stack, and the others follow it away from the stack.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub(crate) struct Scratch {
+    first: isize,
+    direction: isize,
+}
+
+impl Scratch {
+    /// Returns the `n`th temporary cell. Negative `n` count back toward the stack.
+    pub(crate) fn cell(&self, n: isize) -> isize {
+        self.first + n * self.direction
+    }
+
+    /// Returns which temporary cell a cell is, or `None` if it isn't one
+    pub(crate) fn index(&self, cell: isize) -> Option<isize> {
+        let n = (cell - self.first) * self.direction;
+        (n >= 0).then_some(n)
+    }
+}
+
+/// Finds the first `Start` and the last `Top` of a list of instructions
+pub(crate) fn bounds(instructions: &[Instruction]) -> (Option<isize>, Option<isize>) {
+    let start = instructions.iter().find_map(|i| match i {
+        Instruction::Start { cell } => Some(*cell),
+        _ => None,
+    });
+    let top = instructions.iter().rev().find_map(|i| match i {
+        Instruction::Top { cell } => Some(*cell),
+        _ => None,
+    });
+    (start, top)
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::upward()
+    }
+}
@@ -91,15 +91,42 @@
 //! ```
 #![warn(missing_docs)]
 
+mod cache;
+mod compose;
+mod cost;
+mod dot;
+mod forth;
+mod graph;
+mod layout;
+mod linear;
+mod listing;
+mod mapping;
 mod model;
+mod optimize;
+mod options;
 mod parse;
+mod search;
 mod solve;
+mod stacks;
 use models::Brainfuck;
 
 pub mod models;
+pub use cache::SolutionCache;
+pub use compose::compose;
+pub use cost::Cost;
+pub use dot::{to_dot, to_dot_ordered};
+pub use forth::{forth, ForthWords};
+pub use layout::Layout;
+pub use linear::{parse_linear, solve_linear, LinearDiagram};
+pub use listing::{parse_listing, parse_listing_with, ListingError};
+pub use mapping::{solve_mapping, MappingError};
 pub use model::Model;
+pub use optimize::optimize;
+pub use options::SolveOptions;
 pub use parse::{parse, ParseError, StackEffectDiagram};
-pub use solve::{solve, Instruction};
+pub use search::{search, Primitive, SearchError};
+pub use solve::{solve, solve_iter, solve_iter_with, solve_with, Instruction};
+pub use stacks::{parse_stacks, solve_stacks, MultiStackDiagram};
 
 /// Generate a brainfuck program that applies a given [`StackEffectDiagram`](crate::StackEffectDiagram)
 ///
@@ -139,22 +166,100 @@ where
 {
     let diagram = parse(stack_effect)?;
 
-    let instructions = solve(&diagram);
+    let instructions = solve_iter(&diagram);
 
     // For each instruction send it to the backend to generate the program
     Ok(generate(instructions, model))
 }
 
+/// Generate a program to apply a [`LinearDiagram`](crate::LinearDiagram), whose outputs may be
+/// sums and multiples of its inputs.
+///
+/// Outputs other than a single input need a model that
+/// [supports weights](crate::Model::supports_weights), otherwise
+/// [`ParseError::UnsupportedTerm`](crate::ParseError::UnsupportedTerm) is returned.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::autoperm_linear;
+/// use autoperm::models::Brainfuck;
+///
+/// let program = autoperm_linear("a -- a*3", Brainfuck::new());
+///
+/// assert_eq!(program, Ok("[->+<]>[-<+++>]<".to_string()));
+/// ```
+pub fn autoperm_linear<M>(stack_effect: &str, model: M) -> Result<M::Output, ParseError>
+where
+    M: Model,
+{
+    let diagram = parse_linear(stack_effect)?;
+
+    if !model.supports_weights() {
+        let unsupported = diagram
+            .mapping
+            .iter()
+            .position(|terms| !matches!(terms[..], [(_, 1)]));
+        if let Some(id) = unsupported {
+            let (_, pushes) = parse::split(stack_effect)?;
+            return Err(ParseError::UnsupportedTerm {
+                term: pushes.split_whitespace().nth(id).unwrap().to_string(),
+                id,
+            });
+        }
+    }
+
+    let instructions = solve_linear(&diagram);
+
+    Ok(generate(instructions, model))
+}
+
+/// Generate a single program that applies a sequence of stack effect diagrams one after another.
+///
+/// The diagrams are [composed](crate::compose) and solved once, which is usually shorter than
+/// generating each diagram on its own and joining the programs.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::autoperm_sequence;
+/// use autoperm::models::Brainfuck;
+///
+/// // SWAP SWAP does nothing
+/// let program = autoperm_sequence(&["a b -- b a", "a b -- b a"], Brainfuck::new());
+///
+/// assert_eq!(program, Ok("".to_string()));
+/// ```
+pub fn autoperm_sequence<M>(stack_effects: &[&str], model: M) -> Result<M::Output, ParseError>
+where
+    M: Model,
+{
+    let diagrams = stack_effects
+        .iter()
+        .map(|stack_effect| parse(stack_effect))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let diagram = compose(&diagrams);
+
+    let instructions = solve_iter(&diagram);
+
+    Ok(generate(instructions, model))
+}
+
 /// Generate a program from a list of [`Instruction`](crate::Instruction)s using a given [`Model`](crate::Model).
-pub fn generate<M>(instructions: Vec<Instruction>, mut model: M) -> M::Output
+///
+/// The instructions can come from any iterator, such as [`solve_iter`](crate::solve_iter).
+pub fn generate<M, I>(instructions: I, mut model: M) -> M::Output
 where
     M: Model,
+    I: IntoIterator<Item = Instruction>,
 {
     instructions
         .into_iter()
         .for_each(|instruction| match instruction {
             Instruction::Clear { cell } => model.clear(cell),
-            Instruction::Mov { cell, to } => model.mov(cell, to),
+            Instruction::Mov { cell, to } => model.mov(cell, &to),
+            Instruction::WeightedMov { cell, to } => model.weighted_mov(cell, &to),
             Instruction::Start { cell } => model.start(cell),
             Instruction::Top { cell } => model.top(cell),
         });
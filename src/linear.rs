@@ -0,0 +1,230 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    graph::strongly_connected,
+    parse::{input_symbols, split},
+    Instruction, ParseError, StackEffectDiagram,
+};
+
+/// Represents a stack effect diagram whose outputs are linear combinations of its inputs.
+///
+/// # Example
+///
+/// ```
+/// use autoperm::{parse_linear, LinearDiagram};
+///
+/// let diagram = parse_linear("a b -- a+b a-b*2").unwrap();
+///
+/// assert_eq!(diagram, LinearDiagram {
+///     inputs: 2,
+///     mapping: vec![vec![(0, 1), (1, 1)], vec![(0, 1), (1, -2)]],
+/// });
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct LinearDiagram {
+    /// For each output symbol, the starting positions of the inputs it sums and their weights
+    pub mapping: Vec<Vec<(usize, isize)>>,
+    /// The number of input symbols
+    pub inputs: usize,
+}
+
+impl From<StackEffectDiagram> for LinearDiagram {
+    fn from(diagram: StackEffectDiagram) -> Self {
+        LinearDiagram {
+            mapping: diagram.mapping.into_iter().map(|i| vec![(i, 1)]).collect(),
+            inputs: diagram.inputs,
+        }
+    }
+}
+
+/// Parses a [`LinearDiagram`](crate::LinearDiagram).
+///
+/// Each output is written without spaces as a sum or difference of input symbols, each
+/// optionally scaled by an integer, for example `a+b`, `a*3`, `2*a-b`.
+pub fn parse_linear(stack_effect: &str) -> Result<LinearDiagram, ParseError> {
+    let (pops, pushes) = split(stack_effect)?;
+
+    let symbols_to_positions = input_symbols(pops)?;
+
+    let mapping = pushes
+        .split_whitespace()
+        .enumerate()
+        .map(|(id, output)| parse_output(output, id, &symbols_to_positions))
+        .collect::<Result<_, _>>()?;
+
+    Ok(LinearDiagram {
+        mapping,
+        inputs: symbols_to_positions.len(),
+    })
+}
+
+fn parse_output(
+    output: &str,
+    id: usize,
+    symbols_to_positions: &HashMap<&str, usize>,
+) -> Result<Vec<(usize, isize)>, ParseError> {
+    let invalid = || ParseError::InvalidTerm {
+        term: output.to_string(),
+        id,
+    };
+
+    // split the output into signed terms
+    let mut terms = Vec::new();
+    let mut start = 0;
+    let mut sign = 1;
+    for (i, c) in output.char_indices() {
+        if c == '+' || c == '-' {
+            if i > 0 {
+                terms.push((sign, &output[start..i]));
+            }
+            sign = if c == '-' { -1 } else { 1 };
+            start = i + 1;
+        }
+    }
+    terms.push((sign, &output[start..]));
+
+    let mut weights = BTreeMap::new();
+    for (sign, term) in terms {
+        let mut weight: isize = sign;
+        let mut position = None;
+
+        for factor in term.split('*') {
+            if let Ok(n) = factor.parse::<isize>() {
+                weight = weight.checked_mul(n).ok_or_else(invalid)?;
+            } else if factor.is_empty() || position.is_some() {
+                return Err(invalid());
+            } else if let Some(pos) = symbols_to_positions.get(factor) {
+                position = Some(*pos);
+            } else {
+                return Err(ParseError::SymbolNotDefined {
+                    symbol: factor.to_string(),
+                    id,
+                });
+            }
+        }
+
+        let position = position.ok_or_else(invalid)?;
+        let total: &mut isize = weights.entry(position).or_default();
+        *total = total.checked_add(weight).ok_or_else(invalid)?;
+    }
+
+    Ok(weights.into_iter().filter(|(_, w)| *w != 0).collect())
+}
+
+/// Given a [`LinearDiagram`](crate::LinearDiagram) generate a list of instructions to apply that
+/// diagram.
+///
+/// Unlike a [`StackEffectDiagram`](crate::StackEffectDiagram) a cell can be made of several
+/// inputs, so the move graph may contain nested cycles. Each level of nesting uses another
+/// temporary cell above the first one.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::{parse_linear, solve_linear, Instruction};
+///
+/// let diagram = parse_linear("a b -- a+b").unwrap();
+///
+/// assert_eq!(solve_linear(&diagram), vec![
+///     Instruction::Start { cell: 1 },
+///     Instruction::Mov { cell: 1, to: vec![0] },
+///     Instruction::Top { cell: 0 },
+/// ]);
+/// ```
+pub fn solve_linear(diagram: &LinearDiagram) -> Vec<Instruction> {
+    let inputs = diagram.inputs;
+    let outputs = diagram.mapping.len();
+
+    // The index of the first temporary variable. Place it above the highest item
+    let temp = std::cmp::max(inputs, outputs) as isize;
+
+    // The cells and weights each cell is added to, highest first
+    let mut targets: Vec<Vec<(isize, isize)>> = vec![Vec::new(); temp as usize];
+    for (i, terms) in diagram.mapping.iter().enumerate().rev() {
+        for &(j, weight) in terms {
+            targets[j].push((i as isize, weight));
+        }
+    }
+
+    let mut instructions = vec![Instruction::Start {
+        cell: inputs as isize - 1,
+    }];
+
+    let nodes = vec![true; targets.len()];
+    schedule(&targets, &nodes, temp, inputs, &mut instructions);
+
+    instructions.push(Instruction::Top {
+        cell: outputs as isize - 1,
+    });
+
+    instructions
+}
+
+/// Schedules the moves out of a set of nodes, using `temp` and the cells above it to break cycles
+fn schedule(
+    targets: &[Vec<(isize, isize)>],
+    nodes: &[bool],
+    temp: isize,
+    inputs: usize,
+    instructions: &mut Vec<Instruction>,
+) {
+    let components = strongly_connected(
+        targets.len(),
+        (0..targets.len()).filter(|&node| nodes[node]),
+        |node| {
+            targets[node]
+                .iter()
+                .map(|&(target, _)| target as usize)
+                .filter(|&target| nodes[target])
+        },
+    );
+
+    for component in components {
+        if component.len() == 1 {
+            let index = component[0] as isize;
+            let targets = targets[component[0]].clone();
+
+            if targets.is_empty() {
+                if index < inputs as isize {
+                    instructions.push(Instruction::Clear { cell: index });
+                }
+            } else if targets.iter().any(|(cell, _)| *cell == index) {
+                if targets != [(index, 1)] {
+                    instructions.push(Instruction::Mov {
+                        cell: index,
+                        to: vec![temp],
+                    });
+                    instructions.push(weighted(temp, targets));
+                }
+            } else {
+                instructions.push(weighted(index, targets));
+            }
+        } else {
+            // Park one node in the temporary cell, what remains may still contain cycles
+            let (&first, rest) = component.split_first().unwrap();
+
+            instructions.push(Instruction::Mov {
+                cell: first as isize,
+                to: vec![temp],
+            });
+
+            let mut remaining = vec![false; targets.len()];
+            rest.iter().for_each(|&node| remaining[node] = true);
+            schedule(targets, &remaining, temp + 1, inputs, instructions);
+
+            instructions.push(weighted(temp, targets[first].clone()));
+        }
+    }
+}
+
+/// Creates a plain `Mov` when every weight is 1
+fn weighted(cell: isize, to: Vec<(isize, isize)>) -> Instruction {
+    if to.iter().all(|(_, weight)| *weight == 1) {
+        Instruction::Mov {
+            cell,
+            to: to.into_iter().map(|(cell, _)| cell).collect(),
+        }
+    } else {
+        Instruction::WeightedMov { cell, to }
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{models::Listing, Instruction};
+
+/// Returned when [`parse_listing`](crate::parse_listing) fails
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum ListingError {
+    /// Returned when a line is not an instruction
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use autoperm::{parse_listing, ListingError};
+    ///
+    /// let listing = parse_listing("START 1\nJMP 0\nTOP 1");
+    ///
+    /// assert_eq!(listing, Err(ListingError::InvalidLine { line: 2, text: "JMP 0".to_string() }));
+    /// ```
+    InvalidLine {
+        /// The line number, starting at 1
+        line: usize,
+        /// The text of the line
+        text: String,
+    },
+    /// Returned when a cell is named by a symbol the listing doesn't define. The temporary cells
+    /// `T`, `T1`, ... are only defined when the listing has a `TOP`, and other names only when
+    /// they are given to [`parse_listing_with`](crate::parse_listing_with).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use autoperm::{parse_listing, ListingError};
+    ///
+    /// let listing = parse_listing("MOV 0 -> {T}");
+    ///
+    /// assert_eq!(listing, Err(ListingError::UnknownSymbol { line: 1, symbol: "T".to_string() }));
+    /// ```
+    UnknownSymbol {
+        /// The line number, starting at 1
+        line: usize,
+        /// The symbol that could not be resolved
+        symbol: String,
+    },
+}
+
+impl std::fmt::Display for ListingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListingError::InvalidLine { line, text } => {
+                write!(f, "Invalid instruction {} on line {}", text, line)
+            }
+            ListingError::UnknownSymbol { line, symbol } => {
+                write!(f, "Unknown symbol {} on line {}", symbol, line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ListingError {}
+
+/// Parses the listing printed by [`Listing`](crate::models::Listing) back into instructions.
+///
+/// Each line is one of `START n`, `CLEAR n`, `MOV n -> {n, ...}` or `TOP n`, where a cell `n` is
+/// a number or a temporary cell `T`, `T1`, ... past the stack of the default
+/// [`Layout`](crate::Layout). A `MOV` whose destinations have
+/// weights, like `{1*2, 3*-1}`, is a [`WeightedMov`](Instruction::WeightedMov), and missing weights
+/// are 1. Blank lines and anything after `//` are ignored.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::{parse_listing, Instruction};
+///
+/// let listing = "\
+/// START 1
+/// MOV 1 -> {T}  // park b
+/// MOV 0 -> {1}
+/// MOV T -> {0}
+/// TOP 1
+/// ";
+///
+/// assert_eq!(parse_listing(listing), Ok(vec![
+///     Instruction::Start { cell: 1 },
+///     Instruction::Mov { cell: 1, to: vec![2] },
+///     Instruction::Mov { cell: 0, to: vec![1] },
+///     Instruction::Mov { cell: 2, to: vec![0] },
+///     Instruction::Top { cell: 1 },
+/// ]));
+/// ```
+pub fn parse_listing(listing: &str) -> Result<Vec<Instruction>, ListingError> {
+    parse_listing_with(listing, &Listing::new())
+}
+
+/// Parses a listing printed by a [`Listing`], with the same [`names`](Listing::names) and
+/// [`layout`](Listing::layout). The cell of the `i`th item of the stack may be written as the
+/// `i`th name, and the temporary cells are found where the layout puts them.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::{parse_listing, parse_listing_with, Instruction, Layout, ListingError};
+/// use autoperm::models::Listing;
+///
+/// let listing = "START 1\nMOV b -> {T}\nMOV a -> {b}\nMOV T -> {a}\nTOP 1";
+/// let names = Listing::new().names(&["a", "b"]);
+///
+/// assert_eq!(parse_listing_with(listing, &names), parse_listing(
+///     "START 1\nMOV 1 -> {T}\nMOV 0 -> {1}\nMOV T -> {0}\nTOP 1"
+/// ));
+/// assert_eq!(parse_listing(listing), Err(ListingError::UnknownSymbol {
+///     line: 2,
+///     symbol: "b".to_string(),
+/// }));
+///
+/// let listing = "START -1\nMOV b -> {T}\nMOV a -> {b}\nMOV T -> {a}\nTOP -1";
+/// let downward = names.layout(Layout::downward());
+///
+/// assert_eq!(parse_listing_with(listing, &downward), Ok(vec![
+///     Instruction::Start { cell: -1 },
+///     Instruction::Mov { cell: -1, to: vec![-2] },
+///     Instruction::Mov { cell: 0, to: vec![-1] },
+///     Instruction::Mov { cell: -2, to: vec![0] },
+///     Instruction::Top { cell: -1 },
+/// ]));
+/// ```
+pub fn parse_listing_with(
+    listing: &str,
+    model: &Listing,
+) -> Result<Vec<Instruction>, ListingError> {
+    let lines: Vec<(usize, &str)> = listing
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split("//").next().unwrap().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .collect();
+
+    let number = |line: usize, text: &str, words: &[&str]| match words {
+        [_, n] => n.parse::<isize>().map_err(|_| ListingError::InvalidLine {
+            line,
+            text: text.to_string(),
+        }),
+        _ => Err(ListingError::InvalidLine {
+            line,
+            text: text.to_string(),
+        }),
+    };
+    // The temporary cells can only be resolved once the whole stack is known
+    let mut start = None;
+    let mut top = None;
+    for &(line, text) in &lines {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words.first() {
+            Some(&"START") if start.is_none() => start = Some(number(line, text, &words)?),
+            Some(&"TOP") => top = Some(number(line, text, &words)?),
+            _ => {}
+        }
+    }
+    let layout = model.layout;
+    let scratch = layout.scratch(start, top);
+
+    let cell = |line: usize, symbol: &str| -> Result<isize, ListingError> {
+        if let Ok(cell) = symbol.parse() {
+            return Ok(cell);
+        }
+        if let Some(position) = model.names.iter().position(|name| name == symbol) {
+            return Ok(layout.cell(position as isize));
+        }
+
+        let unknown = || ListingError::UnknownSymbol {
+            line,
+            symbol: symbol.to_string(),
+        };
+        let offset = match symbol.strip_prefix('T').ok_or_else(unknown)? {
+            "" => 0,
+            n => n
+                .parse::<isize>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(unknown)?,
+        };
+        Ok(scratch.ok_or_else(unknown)?.cell(offset))
+    };
+
+    let mut instructions = Vec::new();
+    for (line, text) in lines {
+        let invalid = || ListingError::InvalidLine {
+            line,
+            text: text.to_string(),
+        };
+        let words: Vec<&str> = text.split_whitespace().collect();
+
+        let instruction = match words.first() {
+            Some(&"START") => Instruction::Start {
+                cell: number(line, text, &words)?,
+            },
+            Some(&"TOP") => Instruction::Top {
+                cell: number(line, text, &words)?,
+            },
+            Some(&"CLEAR") => match words[..] {
+                [_, symbol] => Instruction::Clear {
+                    cell: cell(line, symbol)?,
+                },
+                _ => return Err(invalid()),
+            },
+            Some(&"MOV") => {
+                let (source, targets) = text["MOV".len()..].split_once("->").ok_or_else(invalid)?;
+                let targets = targets
+                    .trim()
+                    .strip_prefix('{')
+                    .and_then(|t| t.strip_suffix('}'))
+                    .ok_or_else(invalid)?;
+                let source = cell(line, source.trim())?;
+
+                let mut to = Vec::new();
+                let mut weighted = false;
+                for target in targets.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                    let (target, weight) = match target.split_once('*') {
+                        Some((target, weight)) => {
+                            weighted = true;
+                            let weight = weight.trim().parse().map_err(|_| invalid())?;
+                            (target.trim(), weight)
+                        }
+                        None => (target, 1),
+                    };
+                    to.push((cell(line, target)?, weight));
+                }
+
+                if weighted {
+                    Instruction::WeightedMov { cell: source, to }
+                } else {
+                    Instruction::Mov {
+                        cell: source,
+                        to: to.into_iter().map(|(target, _)| target).collect(),
+                    }
+                }
+            }
+            _ => return Err(invalid()),
+        };
+        instructions.push(instruction);
+    }
+
+    Ok(instructions)
+}
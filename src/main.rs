@@ -1,13 +1,95 @@
-use autoperm::autoperm_bf;
+use autoperm::{
+    generate,
+    models::{Brainfuck, BrainfuckCost, Trace},
+    parse, solve, to_dot, to_dot_ordered, Cost, ParseError,
+};
 use itertools::Itertools;
 use std::{env::args, process::exit};
 
+/// What to print for each stack effect diagram
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Output {
+    Brainfuck,
+    /// The brainfuck program followed by a line of statistics
+    Stats,
+    /// The move graph in the DOT language, optionally numbered in instruction order
+    Dot {
+        ordered: bool,
+    },
+    /// The brainfuck program explained step by step with the tape after every instruction
+    Trace,
+}
+
+/// Generates the output for a stack effect diagram
+fn run(stack_effect: &str, output: Output) -> Result<String, ParseError> {
+    let diagram = parse(stack_effect)?;
+    match output {
+        Output::Dot { ordered: false } => return Ok(to_dot(&diagram)),
+        Output::Dot { ordered: true } => return Ok(to_dot_ordered(&diagram)),
+        _ => {}
+    }
+
+    let instructions = solve(&diagram);
+    if output == Output::Trace {
+        // The diagram parsed, so the inputs are the words before the "--"
+        let names: Vec<&str> = stack_effect
+            .split("--")
+            .next()
+            .unwrap()
+            .split_whitespace()
+            .collect();
+        return Ok(generate(instructions, Trace::new().names(&names)));
+    }
+
+    let cost = Cost::new(&instructions);
+    let program = generate(instructions, Brainfuck::new());
+
+    if output == Output::Stats {
+        let bf = BrainfuckCost::new(&program);
+        Ok(format!(
+            "{}\nmovs: {}, clears: {}, temp uses: {}, travel: {}, length: {}, loops: {}, worst case iterations: {}",
+            program,
+            cost.movs,
+            cost.clears,
+            cost.temp_uses,
+            cost.travel,
+            bf.length,
+            bf.loops,
+            bf.worst_case_iterations
+        ))
+    } else {
+        Ok(program)
+    }
+}
+
 fn main() {
-    // if there are args treat them as the input
-    let args = args().skip(1).join(" ");
+    // pull out the flags, the rest of the args are the input
+    let mut output = Output::Brainfuck;
+    let args = args()
+        .skip(1)
+        .filter(|arg| match arg.as_str() {
+            "--stats" => {
+                output = Output::Stats;
+                false
+            }
+            "--dot" => {
+                output = Output::Dot { ordered: false };
+                false
+            }
+            "--trace" => {
+                output = Output::Trace;
+                false
+            }
+            "--dot-ordered" => {
+                output = Output::Dot { ordered: true };
+                false
+            }
+            _ => true,
+        })
+        .join(" ");
 
     if !args.is_empty() {
-        match autoperm_bf(&args) {
+        match run(&args, output) {
             Ok(a) => println!("{}", a),
             Err(e) => {
                 eprintln!("{}", e);
@@ -20,7 +102,7 @@ fn main() {
             // read in the stack effect diagram
             let mut input: String = String::new();
             match std::io::stdin().read_line(&mut input) {
-                Ok(_) => match autoperm_bf(&input) {
+                Ok(_) => match run(&input, output) {
                     Ok(program) => println!("{}\n", program),
                     Err(e) => {
                         eprintln!("{}\n", e);
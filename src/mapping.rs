@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use crate::{
+    graph::MoveGraph,
+    solve::{Cells, Instructions},
+    Instruction,
+};
+
+/// Returned when [`solve_mapping`](crate::solve_mapping) is given a mapping that can't be applied
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum MappingError {
+    /// Returned when a cell is the destination of more than one source
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use autoperm::{solve_mapping, MappingError};
+    ///
+    /// let mapping = HashMap::from([(0, vec![2]), (1, vec![2])]);
+    ///
+    /// assert_eq!(solve_mapping(&mapping, 3), Err(MappingError::DestinationUsedTwice { cell: 2 }));
+    /// ```
+    DestinationUsedTwice {
+        /// The cell with more than one source
+        cell: isize,
+    },
+    /// Returned when the temporary cell is a source or destination of the mapping
+    TempInUse {
+        /// The temporary cell
+        cell: isize,
+    },
+}
+
+impl std::fmt::Display for MappingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MappingError::DestinationUsedTwice { cell } => {
+                write!(
+                    f,
+                    "Cell {} is the destination of more than one source",
+                    cell
+                )
+            }
+            MappingError::TempInUse { cell } => {
+                write!(f, "Temporary cell {} is part of the mapping", cell)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MappingError {}
+
+/// Generate the instructions to move data between arbitrary cells at the same time.
+///
+/// Each source cell in `mapping` is copied to its list of destination cells. A source with no
+/// destinations is cleared, cells that are only destinations are assumed to start empty, and
+/// every cell not in the mapping is left alone. Cells don't need to be contiguous and may be
+/// negative. `temp` must be an empty cell outside of the mapping.
+///
+/// The result uses the same cycle and tree logic as [`solve`](crate::solve) but has no `Start` or
+/// `Top` instruction, since there is no stack.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// use autoperm::{solve_mapping, Instruction};
+///
+/// // Swap the top of the stack with a variable far below it
+/// let mapping = HashMap::from([(3, vec![-10]), (-10, vec![3])]);
+///
+/// assert_eq!(solve_mapping(&mapping, 4), Ok(vec![
+///     Instruction::Mov { cell: 3, to: vec![4] },
+///     Instruction::Mov { cell: -10, to: vec![3] },
+///     Instruction::Mov { cell: 4, to: vec![-10] },
+/// ]));
+/// ```
+pub fn solve_mapping(
+    mapping: &HashMap<isize, Vec<isize>>,
+    temp: isize,
+) -> Result<Vec<Instruction>, MappingError> {
+    // Give every cell a node, in order so that the result doesn't depend on the hash map
+    let mut cells: Vec<isize> = mapping
+        .iter()
+        .flat_map(|(source, destinations)| std::iter::once(source).chain(destinations))
+        .copied()
+        .collect();
+    cells.sort_unstable();
+    cells.dedup();
+
+    if cells.binary_search(&temp).is_ok() {
+        return Err(MappingError::TempInUse { cell: temp });
+    }
+
+    let node = |cell: &isize| cells.binary_search(cell).unwrap();
+
+    let mut keys: Vec<_> = mapping.keys().collect();
+    keys.sort_unstable();
+
+    let mut sources = vec![None; cells.len()];
+    let mut is_source = vec![false; cells.len()];
+    for source in keys {
+        is_source[node(source)] = true;
+
+        for destination in &mapping[source] {
+            let slot = &mut sources[node(destination)];
+            if slot.is_some() {
+                return Err(MappingError::DestinationUsedTwice { cell: *destination });
+            }
+            *slot = Some(node(source));
+        }
+    }
+
+    let graph = MoveGraph::new(sources);
+    let cells = Cells::Sparse {
+        cells,
+        sources: is_source,
+    };
+
+    Ok(Instructions::new(graph, cells, temp).collect())
+}
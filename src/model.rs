@@ -10,7 +10,25 @@ pub trait Model {
     /// Clears a cell to 0
     fn clear(&mut self, cell: isize);
     /// Clears the data in a cell and copies it to a list of other cells
-    fn mov(&mut self, cell: isize, to: Vec<isize>);
+    fn mov(&mut self, cell: isize, to: &[isize]);
+    /// Clears the data in a cell and adds multiples of it to a list of other cells
+    ///
+    /// The default implementation only supports weights of 1, which it forwards to [`mov`](Model::mov).
+    /// Models that override it should also override [`supports_weights`](Model::supports_weights).
+    fn weighted_mov(&mut self, cell: isize, to: &[(isize, isize)]) {
+        assert!(
+            to.iter().all(|(_, weight)| *weight == 1),
+            "this model does not support weighted moves"
+        );
+        let to: Vec<isize> = to.iter().map(|(cell, _)| *cell).collect();
+        self.mov(cell, &to)
+    }
+    /// Returns whether moves add to their destinations and [`weighted_mov`](Model::weighted_mov)
+    /// supports any weight, which [`LinearDiagram`](crate::LinearDiagram)s with sums and multiples
+    /// need. Models that only rearrange values return `false`.
+    fn supports_weights(&self) -> bool {
+        false
+    }
     /// Changes the top of the stack to a given index
     fn top(&mut self, cell: isize);
     /// Returns the final program output.
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use crate::{
+    layout::{bounds, Scratch},
+    Instruction, Layout,
+};
+
+/// A write of a moved value to one of its destinations
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Write {
+    pub(super) target: isize,
+    pub(super) weight: isize,
+    /// Whether the target still holds a value, so the moved value is added to it
+    pub(super) add: bool,
+}
+
+/// The statements of a backend whose cells are variables or memory that an assignment
+/// overwrites, as rendered from an [`Assignments`]
+pub(super) trait Assign {
+    /// Moves a cell to its destinations
+    fn mov(&mut self, cell: isize, writes: &[Write]);
+
+    /// Swaps two cells on the stack without the temporary cell, returning `false` if the backend
+    /// would rather use three moves
+    fn swap(&mut self, _a: isize, _b: isize) -> bool {
+        false
+    }
+
+    /// Sets a cell to 0
+    fn zero(&mut self, cell: isize);
+
+    /// Moves the top of the stack by a number of cells, toward lower cells when it is negative
+    fn shift(&mut self, cells: isize);
+}
+
+/// The instructions of a program for an [`Assign`] backend. Nothing is ever cleared, instead the
+/// cells that are empty when the program ends are set to 0.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Assignments {
+    instructions: Vec<Instruction>,
+}
+
+impl Assignments {
+    pub(super) fn start(&mut self, cell: isize) {
+        self.instructions.push(Instruction::Start { cell });
+    }
+
+    pub(super) fn clear(&mut self, cell: isize) {
+        self.instructions.push(Instruction::Clear { cell });
+    }
+
+    pub(super) fn mov(&mut self, cell: isize, to: &[(isize, isize)]) {
+        self.instructions.push(Instruction::WeightedMov {
+            cell,
+            to: to.to_vec(),
+        });
+    }
+
+    pub(super) fn top(&mut self, cell: isize) {
+        self.instructions.push(Instruction::Top { cell });
+    }
+
+    /// The top of the stack when the program starts
+    pub(super) fn start_cell(&self) -> isize {
+        bounds(&self.instructions).0.unwrap_or(0)
+    }
+
+    /// The top of the stack when the program ends
+    pub(super) fn top_cell(&self) -> Option<isize> {
+        bounds(&self.instructions).1
+    }
+
+    /// The cells past both the starting and the final top of the stack, which are only used for
+    /// temporary values
+    pub(super) fn scratch(&self, layout: &Layout) -> Option<Scratch> {
+        layout.scratch_of(&self.instructions)
+    }
+
+    /// Renders the program, solved for a layout, through a backend
+    pub(super) fn render(&self, layout: &Layout, backend: &mut impl Assign) {
+        let start = self.start_cell();
+        let scratch = self.scratch(layout);
+        let is_temp = |cell: isize| scratch.and_then(|scratch| scratch.index(cell)).is_some();
+        // Whether a cell is an item of the stack up to a top
+        let on_stack = |cell: isize, top: isize| {
+            layout
+                .item(cell)
+                .is_some_and(|position| position <= layout.position(top))
+        };
+
+        // Whether each cell the program has touched holds a value. Cells past the stack and
+        // between its items are garbage rather than 0, so they are assigned to instead of added
+        // to.
+        let mut full = HashMap::new();
+
+        let mut i = 0;
+        while i < self.instructions.len() {
+            // a → T, b → a, T → b swaps two cells
+            if let [Instruction::WeightedMov { cell: a, to: t }, Instruction::WeightedMov { cell: b, to: a2 }, Instruction::WeightedMov { cell: t2, to: b2 }, ..] =
+                &self.instructions[i..]
+            {
+                if t[..] == [(*t2, 1)]
+                    && a2[..] == [(*a, 1)]
+                    && b2[..] == [(*b, 1)]
+                    && is_temp(*t2)
+                    && backend.swap(*a, *b)
+                {
+                    full.insert(*a, true);
+                    full.insert(*b, true);
+                    i += 3;
+                    continue;
+                }
+            }
+
+            match &self.instructions[i] {
+                Instruction::Clear { cell } => {
+                    full.insert(*cell, false);
+                }
+                Instruction::WeightedMov { cell, to } => {
+                    let writes: Vec<Write> = to
+                        .iter()
+                        .map(|&(target, weight)| Write {
+                            target,
+                            weight,
+                            add: *full.get(&target).unwrap_or(&on_stack(target, start)),
+                        })
+                        .collect();
+                    backend.mov(*cell, &writes);
+
+                    for write in writes {
+                        full.insert(write.target, true);
+                    }
+                    full.insert(*cell, false);
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        if let Some(top) = self.top_cell() {
+            // Cells left on the stack that should be empty still hold their old values
+            let mut zeroes: Vec<isize> = full
+                .into_iter()
+                .filter(|&(cell, full)| !full && on_stack(cell, top))
+                .map(|(cell, _)| cell)
+                .collect();
+            zeroes.sort();
+            for cell in zeroes {
+                backend.zero(cell);
+            }
+
+            if top != start {
+                backend.shift(top - start);
+            }
+        }
+    }
+}
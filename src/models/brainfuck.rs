@@ -0,0 +1,352 @@
+use std::cmp::Ordering;
+
+use crate::{Layout, Model};
+
+/// How [`Brainfuck`] clears a cell
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+pub enum ClearLoop {
+    /// `[-]`
+    #[default]
+    Decrement,
+    /// `[+]`, which only works when cells wrap around
+    Increment,
+}
+
+/// Where the pointer of a [`Brainfuck`] program starts and ends
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+pub enum PointerPosition {
+    /// At the top item of the stack
+    #[default]
+    Top,
+    /// At the first free cell above the stack, where the [`Layout`](crate::Layout) would put the
+    /// next item
+    AboveTop,
+}
+
+/// The conventions of the Brainfuck dialect a [`Brainfuck`] model generates
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::autoperm;
+/// use autoperm::models::{Brainfuck, BrainfuckOptions, ClearLoop, PointerPosition};
+///
+/// let options = BrainfuckOptions::new()
+///     .clear(ClearLoop::Increment)
+///     .increment_first(true)
+///     .pointer(PointerPosition::AboveTop);
+///
+/// let program = autoperm("a b c -- c", Brainfuck::with_options(options)).unwrap();
+///
+/// assert_eq!(program, "<<<[+]>[+]>[<<+>>-]<");
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct BrainfuckOptions {
+    clear: ClearLoop,
+    increment_first: bool,
+    cell_bits: Option<u32>,
+    pointer: PointerPosition,
+    /// The distance between the cells of neighbouring items
+    step: isize,
+}
+
+impl BrainfuckOptions {
+    /// Creates the options of the default dialect: `[-]` clears, loops decrement first, cells
+    /// are 8 bits and the pointer starts and ends at the top of an upward stack
+    pub fn new() -> Self {
+        Self {
+            clear: ClearLoop::Decrement,
+            increment_first: false,
+            cell_bits: Some(8),
+            pointer: PointerPosition::Top,
+            step: 1,
+        }
+    }
+
+    /// Sets how cells are cleared
+    pub fn clear(mut self, clear: ClearLoop) -> Self {
+        self.clear = clear;
+        self
+    }
+
+    /// Sets whether a move loop adds to its destinations before decrementing, `[>+<-]` instead
+    /// of `[->+<]`
+    pub fn increment_first(mut self, increment_first: bool) -> Self {
+        self.increment_first = increment_first;
+        self
+    }
+
+    /// Sets the number of bits in a cell. `None` means cells are unbounded and never wrap
+    /// around, so only [`ClearLoop::Decrement`] can clear them.
+    ///
+    /// Cells that wrap around add a weight in whichever direction is shorter, so with 8 bits a
+    /// weight of 255 is written as a single `-`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is `Some(0)`
+    pub fn cell_bits(mut self, bits: Option<u32>) -> Self {
+        assert!(bits != Some(0), "cells must have at least 1 bit");
+        self.cell_bits = bits;
+        self
+    }
+
+    /// Sets where the pointer starts and ends
+    pub fn pointer(mut self, pointer: PointerPosition) -> Self {
+        self.pointer = pointer;
+        self
+    }
+
+    /// Sets the [`Layout`](crate::Layout) the instructions were solved with, which decides where
+    /// [`PointerPosition::AboveTop`] is
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use autoperm::{generate, parse, solve_with, Layout, SolveOptions};
+    /// use autoperm::models::{Brainfuck, BrainfuckOptions, PointerPosition};
+    ///
+    /// let layout = Layout::downward();
+    /// let options = BrainfuckOptions::new()
+    ///     .pointer(PointerPosition::AboveTop)
+    ///     .layout(layout);
+    ///
+    /// let instructions = solve_with(&parse("a b -- b").unwrap(), &SolveOptions::new().layout(layout));
+    /// let program = generate(instructions, Brainfuck::with_options(options));
+    ///
+    /// assert_eq!(program, ">>[-]<[->+<]");
+    /// ```
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.step = layout.cell(1) - layout.cell(0);
+        self
+    }
+}
+
+impl Default for BrainfuckOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// This crate was originally created for Brainfuck.
+///
+/// This Brainfuck [`Model`](crate::Model) is included for backwards compatibility and demonstration purposes.
+#[derive(Debug, Clone)]
+pub struct Brainfuck {
+    program: String,
+    ptr: isize,
+    options: BrainfuckOptions,
+}
+
+impl Brainfuck {
+    /// Creates a new model
+    pub fn new() -> Self {
+        Self::with_options(BrainfuckOptions::new())
+    }
+
+    /// Creates a new model for a Brainfuck dialect
+    ///
+    /// # Panics
+    ///
+    /// Panics if cells are cleared with [`ClearLoop::Increment`] but are unbounded
+    pub fn with_options(options: BrainfuckOptions) -> Self {
+        assert!(
+            options.clear == ClearLoop::Decrement || options.cell_bits.is_some(),
+            "[+] never clears unbounded cells"
+        );
+        Self {
+            program: String::new(),
+            ptr: 0,
+            options,
+        }
+    }
+
+    /// The program generated so far
+    pub(super) fn program(&self) -> &str {
+        &self.program
+    }
+
+    /// The cell the pointer is at when it is at the top of the stack
+    fn pointer(&self, top: isize) -> isize {
+        match self.options.pointer {
+            PointerPosition::Top => top,
+            PointerPosition::AboveTop => top + self.options.step,
+        }
+    }
+
+    /// The shortest weight that adds the same to a cell as `weight`
+    fn wrap(&self, weight: isize) -> isize {
+        match self.options.cell_bits {
+            Some(bits) if bits < isize::BITS - 1 => {
+                let modulus = 1 << bits;
+                match weight.rem_euclid(modulus) {
+                    weight if weight > modulus / 2 => weight - modulus,
+                    weight => weight,
+                }
+            }
+            _ => weight,
+        }
+    }
+
+    fn shift_to(&mut self, cell: isize) {
+        let diff = cell - self.ptr;
+        match diff.cmp(&0) {
+            Ordering::Less => self.program += &"<".repeat(diff.unsigned_abs()),
+            Ordering::Equal => (),
+            Ordering::Greater => self.program += &">".repeat(diff.unsigned_abs()),
+        }
+        self.ptr = cell;
+    }
+
+    /// Loops until a cell is empty, adding to each destination every iteration
+    fn move_loop(&mut self, cell: isize, to: &[(isize, isize)]) {
+        self.shift_to(cell);
+
+        self.program += "[";
+        if !self.options.increment_first {
+            self.program += "-";
+        }
+
+        for &(position, weight) in to {
+            self.shift_to(position);
+            let weight = self.wrap(weight);
+            match weight.cmp(&0) {
+                Ordering::Less => self.program += &"-".repeat(weight.unsigned_abs()),
+                Ordering::Equal => (),
+                Ordering::Greater => self.program += &"+".repeat(weight.unsigned_abs()),
+            }
+        }
+
+        self.shift_to(cell);
+
+        if self.options.increment_first {
+            self.program += "-";
+        }
+        self.program += "]";
+    }
+}
+
+impl Default for Brainfuck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Model for Brainfuck {
+    type Output = String;
+
+    fn start(&mut self, cell: isize) {
+        self.ptr = self.pointer(cell);
+    }
+
+    fn clear(&mut self, cell: isize) {
+        self.shift_to(cell);
+        self.program += match self.options.clear {
+            ClearLoop::Decrement => "[-]",
+            ClearLoop::Increment => "[+]",
+        };
+    }
+
+    fn mov(&mut self, cell: isize, to: &[isize]) {
+        let to: Vec<(isize, isize)> = to.iter().map(|&position| (position, 1)).collect();
+        self.move_loop(cell, &to);
+    }
+
+    fn weighted_mov(&mut self, cell: isize, to: &[(isize, isize)]) {
+        self.move_loop(cell, to);
+    }
+
+    fn supports_weights(&self) -> bool {
+        true
+    }
+
+    fn top(&mut self, cell: isize) {
+        let cell = self.pointer(cell);
+        self.shift_to(cell);
+    }
+
+    fn finish(self) -> Self::Output {
+        self.program
+    }
+}
+
+/// Statistics about a generated Brainfuck program
+///
+/// # Example
+///
+/// ```
+/// use autoperm::autoperm_bf;
+/// use autoperm::models::BrainfuckCost;
+///
+/// let program = autoperm_bf("a b -- b a").unwrap();
+///
+/// assert_eq!(BrainfuckCost::new(&program), BrainfuckCost {
+///     length: 24,
+///     loops: 3,
+///     worst_case_iterations: 3 * 255,
+/// });
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct BrainfuckCost {
+    /// The number of characters in the program
+    pub length: usize,
+    /// The number of loops in the program
+    pub loops: usize,
+    /// The most loop iterations the program can run, with 8-bit cells unless the cost was
+    /// computed [`with_cell_bits`](BrainfuckCost::with_cell_bits)
+    pub worst_case_iterations: usize,
+}
+
+impl BrainfuckCost {
+    /// Computes the statistics of a Brainfuck program with 8-bit cells
+    pub fn new(program: &str) -> Self {
+        Self::with_cell_bits(program, Some(8))
+    }
+
+    /// Computes the statistics of a Brainfuck program with cells of the given number of bits, as
+    /// set by [`BrainfuckOptions::cell_bits`]. Unbounded cells have no worst case, so it saturates
+    /// at `usize::MAX` as soon as there is a loop.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use autoperm::autoperm_bf;
+    /// use autoperm::models::BrainfuckCost;
+    ///
+    /// let program = autoperm_bf("a b -- b a").unwrap();
+    /// let cost = BrainfuckCost::with_cell_bits(&program, Some(16));
+    ///
+    /// assert_eq!(cost.worst_case_iterations, 3 * 65535);
+    /// ```
+    pub fn with_cell_bits(program: &str, bits: Option<u32>) -> Self {
+        let mut cost = BrainfuckCost {
+            length: program.len(),
+            ..Default::default()
+        };
+        let largest = match bits {
+            Some(bits) if bits < usize::BITS => (1 << bits) - 1,
+            _ => usize::MAX,
+        };
+
+        // A loop runs at most once for each value of a cell every time the loop around it
+        // iterates
+        let mut entries = vec![1usize];
+        for c in program.chars() {
+            match c {
+                '[' => {
+                    cost.loops += 1;
+                    let iterations = entries.last().unwrap().saturating_mul(largest);
+                    cost.worst_case_iterations =
+                        cost.worst_case_iterations.saturating_add(iterations);
+                    entries.push(iterations);
+                }
+                ']' if entries.len() > 1 => {
+                    entries.pop();
+                }
+                _ => {}
+            }
+        }
+
+        cost
+    }
+}
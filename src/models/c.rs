@@ -0,0 +1,232 @@
+use std::collections::HashSet;
+
+use crate::{layout::Scratch, Layout, Model};
+
+use super::assign::{Assign, Assignments, Write};
+
+/// Emits C statements that apply a diagram to a stack in memory.
+///
+/// By default the stack is addressed through a `cell_t *sp` that points at the top of the stack,
+/// and `sp` is moved to the new top at the end. The stack can also be an array indexed by a
+/// variable. Each cell is one `cell_t`, so a diagram solved for another [`layout`](C::layout) may
+/// grow toward lower addresses or leave room between its items.
+///
+/// C variables are overwritten by an assignment, so nothing is ever cleared and each moved value
+/// is simply assigned to its destinations, or added with `+=` to a cell that still holds a value.
+/// The temporary cell past the stack becomes a local variable, declared the first time it is
+/// used.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::autoperm;
+/// use autoperm::models::C;
+///
+/// let program = autoperm("a b -- b a", C::new()).unwrap();
+///
+/// assert_eq!(program, "\
+/// cell_t tmp = sp[0];
+/// sp[0] = sp[-1];
+/// sp[-1] = tmp;
+/// ");
+///
+/// let program = autoperm("a b c -- c", C::new().array("stack", "top").cell_type("int")).unwrap();
+///
+/// assert_eq!(program, "\
+/// stack[top - 2] = stack[top];
+/// top -= 2;
+/// ");
+/// ```
+///
+/// A stack that grows toward lower addresses is solved and emitted with the same layout:
+///
+/// ```
+/// use autoperm::{generate, parse, solve_with, Layout, SolveOptions};
+/// use autoperm::models::C;
+///
+/// let layout = Layout::downward();
+/// let instructions = solve_with(&parse("a b -- b a").unwrap(), &SolveOptions::new().layout(layout));
+///
+/// assert_eq!(generate(instructions, C::new().layout(layout)), "\
+/// cell_t tmp = sp[0];
+/// sp[0] = sp[1];
+/// sp[1] = tmp;
+/// ");
+/// ```
+#[derive(Debug, Clone)]
+pub struct C {
+    cell_type: String,
+    stack: String,
+    index: Option<String>,
+    layout: Layout,
+    temp: String,
+    program: Assignments,
+}
+
+impl C {
+    /// Creates a new model using `cell_t *sp`
+    pub fn new() -> Self {
+        Self {
+            cell_type: "cell_t".to_string(),
+            stack: "sp".to_string(),
+            index: None,
+            layout: Layout::upward(),
+            temp: "tmp".to_string(),
+            program: Assignments::default(),
+        }
+    }
+
+    /// Sets the type of a cell, used to declare the temporary variable
+    pub fn cell_type(mut self, cell_type: &str) -> Self {
+        self.cell_type = cell_type.to_string();
+        self
+    }
+
+    /// Addresses the stack through a pointer to the top of the stack
+    pub fn pointer(mut self, pointer: &str) -> Self {
+        self.stack = pointer.to_string();
+        self.index = None;
+        self
+    }
+
+    /// Addresses the stack as an array with a variable holding the index of the top of the stack
+    pub fn array(mut self, array: &str, index: &str) -> Self {
+        self.stack = array.to_string();
+        self.index = Some(index.to_string());
+        self
+    }
+
+    /// Sets the [`Layout`](crate::Layout) the instructions were solved with, which decides which
+    /// cells are temporary variables and which cells of the stack start out holding a value
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Sets the name of the temporary variable
+    pub fn temp(mut self, temp: &str) -> Self {
+        self.temp = temp.to_string();
+        self
+    }
+
+    /// Returns the C expression for a cell. Cells past both the starting and the final top of
+    /// the stack are local variables.
+    fn lvalue(&self, cell: isize, start: isize, scratch: Option<Scratch>) -> String {
+        match scratch.and_then(|scratch| scratch.index(cell)) {
+            Some(0) => return self.temp.clone(),
+            Some(n) => return format!("{}{}", self.temp, n),
+            None => {}
+        }
+
+        let offset = cell - start;
+
+        match &self.index {
+            None => format!("{}[{}]", self.stack, offset),
+            Some(index) => match offset {
+                0 => format!("{}[{}]", self.stack, index),
+                offset if offset < 0 => format!("{}[{} - {}]", self.stack, index, -offset),
+                offset => format!("{}[{} + {}]", self.stack, index, offset),
+            },
+        }
+    }
+}
+
+impl Default for C {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The statements of a [`C`] program
+struct Statements<'a> {
+    model: &'a C,
+    start: isize,
+    scratch: Option<Scratch>,
+    declared: HashSet<isize>,
+    program: String,
+}
+
+impl Statements<'_> {
+    fn lvalue(&self, cell: isize) -> String {
+        self.model.lvalue(cell, self.start, self.scratch)
+    }
+}
+
+impl Assign for Statements<'_> {
+    fn mov(&mut self, cell: isize, writes: &[Write]) {
+        let value = self.lvalue(cell);
+        for write in writes {
+            let lvalue = self.lvalue(write.target);
+            let value = match write.weight {
+                1 => value.clone(),
+                weight => format!("{} * {}", value, weight),
+            };
+
+            if write.add {
+                self.program += &format!("{} += {};\n", lvalue, value);
+            } else if self
+                .scratch
+                .is_some_and(|scratch| scratch.index(write.target).is_some())
+                && self.declared.insert(write.target)
+            {
+                self.program += &format!("{} {} = {};\n", self.model.cell_type, lvalue, value);
+            } else {
+                self.program += &format!("{} = {};\n", lvalue, value);
+            }
+        }
+    }
+
+    fn zero(&mut self, cell: isize) {
+        self.program += &format!("{} = 0;\n", self.lvalue(cell));
+    }
+
+    fn shift(&mut self, cells: isize) {
+        let variable = self.model.index.as_ref().unwrap_or(&self.model.stack);
+        if cells < 0 {
+            self.program += &format!("{} -= {};\n", variable, -cells);
+        } else {
+            self.program += &format!("{} += {};\n", variable, cells);
+        }
+    }
+}
+
+impl Model for C {
+    type Output = String;
+
+    fn start(&mut self, cell: isize) {
+        self.program.start(cell);
+    }
+
+    fn clear(&mut self, cell: isize) {
+        self.program.clear(cell);
+    }
+
+    fn mov(&mut self, cell: isize, to: &[isize]) {
+        let to: Vec<(isize, isize)> = to.iter().map(|&target| (target, 1)).collect();
+        self.program.mov(cell, &to);
+    }
+
+    fn weighted_mov(&mut self, cell: isize, to: &[(isize, isize)]) {
+        self.program.mov(cell, to);
+    }
+
+    fn supports_weights(&self) -> bool {
+        true
+    }
+
+    fn top(&mut self, cell: isize) {
+        self.program.top(cell);
+    }
+
+    fn finish(self) -> Self::Output {
+        let mut statements = Statements {
+            model: &self,
+            start: self.program.start_cell(),
+            scratch: self.program.scratch(&self.layout),
+            declared: HashSet::new(),
+            program: String::new(),
+        };
+        self.program.render(&self.layout, &mut statements);
+        statements.program
+    }
+}
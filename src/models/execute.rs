@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+
+use crate::Model;
+
+/// Applies the instructions directly to a `Vec<T>` whose last item is the top of the stack.
+///
+/// A value moved to one cell is moved, a value moved to several cells is cloned, and a cleared
+/// cell is set to `T::default()`. Temporary cells above the stack are pushed as needed and the
+/// vector is truncated to the new top of the stack. Moves replace their destinations rather than
+/// adding to them, so weighted moves are not supported.
+///
+/// Without a `Start` the cells are the indices of the vector. Without a `Top` the vector keeps
+/// every item the program wrote, and only the empty items it pushed, like temporary cells, are
+/// removed from the end.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::autoperm;
+/// use autoperm::models::Execute;
+///
+/// let mut stack = vec!["x".to_string(), "a".to_string(), "b".to_string()];
+/// autoperm("a b -- b a b", Execute::new(&mut stack)).unwrap();
+///
+/// assert_eq!(stack, ["x", "b", "a", "b"]);
+/// ```
+#[derive(Debug)]
+pub struct Execute<'a, T> {
+    stack: &'a mut Vec<T>,
+    /// The index of cell 0
+    base: isize,
+    /// The length of the vector before the program
+    len: usize,
+    /// The indices of the items the program pushed that hold no value
+    empty: HashSet<usize>,
+    top: bool,
+}
+
+impl<'a, T: Clone + Default> Execute<'a, T> {
+    /// Creates a new model that applies the instructions to `stack`
+    pub fn new(stack: &'a mut Vec<T>) -> Self {
+        let len = stack.len();
+        Self {
+            stack,
+            base: 0,
+            len,
+            empty: HashSet::new(),
+            top: false,
+        }
+    }
+
+    /// Returns the item a cell refers to, growing the stack to reach it. `full` is whether the
+    /// item holds a value afterwards.
+    fn get(&mut self, cell: isize, full: bool) -> &mut T {
+        let index = usize::try_from(self.base + cell).expect("cell below the bottom of the stack");
+        if index >= self.stack.len() {
+            self.empty.extend(self.stack.len()..index);
+            self.stack.resize(index + 1, T::default());
+        }
+        if index >= self.len {
+            if full {
+                self.empty.remove(&index);
+            } else {
+                self.empty.insert(index);
+            }
+        }
+        &mut self.stack[index]
+    }
+}
+
+impl<'a, T: Clone + Default> Model for Execute<'a, T> {
+    type Output = &'a mut Vec<T>;
+
+    fn start(&mut self, cell: isize) {
+        self.base = self.stack.len() as isize - 1 - cell;
+    }
+
+    fn clear(&mut self, cell: isize) {
+        *self.get(cell, false) = T::default();
+    }
+
+    fn mov(&mut self, cell: isize, to: &[isize]) {
+        let value = std::mem::take(self.get(cell, false));
+        if let Some((&last, rest)) = to.split_last() {
+            for &target in rest {
+                *self.get(target, true) = value.clone();
+            }
+            *self.get(last, true) = value;
+        }
+    }
+
+    fn top(&mut self, cell: isize) {
+        let len = usize::try_from(self.base + cell + 1).expect("top below the bottom of the stack");
+        self.stack.resize(len, T::default());
+        self.top = true;
+    }
+
+    fn finish(self) -> Self::Output {
+        if !self.top {
+            while self.stack.len() > self.len && self.empty.contains(&(self.stack.len() - 1)) {
+                self.stack.pop();
+            }
+        }
+        self.stack
+    }
+}
@@ -0,0 +1,154 @@
+use itertools::Itertools;
+
+use crate::{Instruction, Layout, Model};
+
+/// Prints the instructions as a listing, one instruction per line, in the form used by
+/// explanation.md.
+///
+/// Once the stack is known from `Top`, the temporary cells past it are printed as `T`, `T1`, ...
+/// instead of numbers, in the places the [`layout`](Listing::layout) puts them. With
+/// [`names`](Listing::names) the cell of the `i`th item of the stack is printed as the `i`th name.
+/// [`parse_listing`](crate::parse_listing) and [`parse_listing_with`](crate::parse_listing_with)
+/// read the listing back.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::autoperm;
+/// use autoperm::models::Listing;
+///
+/// let listing = autoperm("a b c -- c a a", Listing::new()).unwrap();
+///
+/// assert_eq!(listing, "\
+/// START 2
+/// CLEAR 1
+/// MOV 2 -> {T}
+/// MOV 0 -> {2, 1}
+/// MOV T -> {0}
+/// TOP 2
+/// ");
+///
+/// let listing = autoperm("a b -- b a", Listing::new().names(&["a", "b"])).unwrap();
+///
+/// assert_eq!(listing, "\
+/// START 1
+/// MOV b -> {T}
+/// MOV a -> {b}
+/// MOV T -> {a}
+/// TOP 1
+/// ");
+/// ```
+///
+/// A diagram solved for another [`Layout`](crate::Layout) needs the same layout to find its
+/// temporary cell:
+///
+/// ```
+/// use autoperm::{generate, parse, solve_with, Layout, SolveOptions};
+/// use autoperm::models::Listing;
+///
+/// let layout = Layout::downward();
+/// let instructions = solve_with(&parse("a b -- b a").unwrap(), &SolveOptions::new().layout(layout));
+///
+/// assert_eq!(generate(instructions, Listing::new().layout(layout)), "\
+/// START -1
+/// MOV -1 -> {T}
+/// MOV 0 -> {-1}
+/// MOV T -> {0}
+/// TOP -1
+/// ");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Listing {
+    pub(crate) names: Vec<String>,
+    pub(crate) layout: Layout,
+    instructions: Vec<Instruction>,
+}
+
+impl Listing {
+    /// Creates a new model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the names printed for the items of the stack from the bottom up, instead of the
+    /// numbers of their cells. The names should not be numbers or look like a temporary cell.
+    pub fn names<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.names = names.iter().map(|name| name.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Sets the [`Layout`](crate::Layout) the instructions were solved with, which decides where
+    /// the temporary cells and the named items are
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+}
+
+impl Model for Listing {
+    type Output = String;
+
+    fn start(&mut self, cell: isize) {
+        self.instructions.push(Instruction::Start { cell });
+    }
+
+    fn clear(&mut self, cell: isize) {
+        self.instructions.push(Instruction::Clear { cell });
+    }
+
+    fn mov(&mut self, cell: isize, to: &[isize]) {
+        self.instructions.push(Instruction::Mov {
+            cell,
+            to: to.to_vec(),
+        });
+    }
+
+    fn weighted_mov(&mut self, cell: isize, to: &[(isize, isize)]) {
+        self.instructions.push(Instruction::WeightedMov {
+            cell,
+            to: to.to_vec(),
+        });
+    }
+
+    fn supports_weights(&self) -> bool {
+        true
+    }
+
+    fn top(&mut self, cell: isize) {
+        self.instructions.push(Instruction::Top { cell });
+    }
+
+    fn finish(self) -> Self::Output {
+        let scratch = self.layout.scratch_of(&self.instructions);
+
+        let name = |cell: isize| match scratch.and_then(|scratch| scratch.index(cell)) {
+            Some(0) => "T".to_string(),
+            Some(n) => format!("T{}", n),
+            None => match self.layout.item(cell).and_then(|i| usize::try_from(i).ok()) {
+                Some(i) if i < self.names.len() => self.names[i].clone(),
+                _ => cell.to_string(),
+            },
+        };
+
+        self.instructions
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Start { cell } => format!("START {}\n", cell),
+                Instruction::Clear { cell } => format!("CLEAR {}\n", name(*cell)),
+                Instruction::Mov { cell, to } => format!(
+                    "MOV {} -> {{{}}}\n",
+                    name(*cell),
+                    to.iter().map(|&target| name(target)).join(", ")
+                ),
+                Instruction::WeightedMov { cell, to } => format!(
+                    "MOV {} -> {{{}}}\n",
+                    name(*cell),
+                    to.iter()
+                        .map(|&(target, weight)| format!("{}*{}", name(target), weight))
+                        .join(", ")
+                ),
+                Instruction::Top { cell } => format!("TOP {}\n", cell),
+            })
+            .collect()
+    }
+}
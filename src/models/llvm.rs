@@ -0,0 +1,191 @@
+use std::collections::BTreeSet;
+
+use crate::{Layout, Model};
+
+use super::sums::{Sums, Value};
+
+/// Emits an LLVM IR function that applies a diagram to a stack of integers in memory.
+///
+/// The function takes a `ptr` to the top of the stack and returns a pointer to the new top. The
+/// stack grows toward higher addresses. Rather than translating every move, the model follows
+/// which inputs each cell holds, so the function loads every input it needs, computes the outputs
+/// as SSA values and stores only the cells that change. The temporary cells above the stack never
+/// touch memory.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::autoperm;
+/// use autoperm::models::LlvmIr;
+///
+/// let function = autoperm("a b -- b a b", LlvmIr::new()).unwrap();
+///
+/// assert_eq!(function, "\
+/// define ptr @shuffle(ptr %sp) {
+/// entry:
+///   %p0 = getelementptr i64, ptr %sp, i64 -1
+///   %v0 = load i64, ptr %p0
+///   %v1 = load i64, ptr %sp
+///   store i64 %v1, ptr %p0
+///   store i64 %v0, ptr %sp
+///   %p2 = getelementptr i64, ptr %sp, i64 1
+///   store i64 %v1, ptr %p2
+///   %top = getelementptr i64, ptr %sp, i64 1
+///   ret ptr %top
+/// }
+/// ");
+/// ```
+#[derive(Debug, Clone)]
+pub struct LlvmIr {
+    name: String,
+    cell_type: String,
+    cells: Sums,
+    start: Option<isize>,
+    top: Option<isize>,
+}
+
+impl LlvmIr {
+    /// Creates a new model for a function named `shuffle` over `i64` cells
+    pub fn new() -> Self {
+        Self {
+            name: "shuffle".to_string(),
+            cell_type: "i64".to_string(),
+            cells: Sums::default(),
+            start: None,
+            top: None,
+        }
+    }
+
+    /// Sets the name of the function, without the `@`
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Sets the integer type of a cell, such as `i8`
+    pub fn cell_type(mut self, cell_type: &str) -> Self {
+        self.cell_type = cell_type.to_string();
+        self
+    }
+}
+
+impl Default for LlvmIr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Model for LlvmIr {
+    type Output = String;
+
+    fn start(&mut self, cell: isize) {
+        self.start = Some(cell);
+        self.cells = Sums::start(&Layout::upward(), cell);
+    }
+
+    fn clear(&mut self, cell: isize) {
+        self.cells.clear(cell);
+    }
+
+    fn mov(&mut self, cell: isize, to: &[isize]) {
+        let to: Vec<(isize, isize)> = to.iter().map(|&target| (target, 1)).collect();
+        self.weighted_mov(cell, &to);
+    }
+
+    fn weighted_mov(&mut self, cell: isize, to: &[(isize, isize)]) {
+        self.cells.mov(cell, to);
+    }
+
+    fn supports_weights(&self) -> bool {
+        true
+    }
+
+    fn top(&mut self, cell: isize) {
+        self.top = Some(cell);
+    }
+
+    fn finish(self) -> Self::Output {
+        let start = self.start.unwrap_or(-1);
+        let top = self.top.unwrap_or(start);
+        let ty = &self.cell_type;
+
+        let mut lines = Vec::new();
+
+        // Pointers to the cells, relative to the top of the stack
+        let mut pointers = BTreeSet::new();
+        let mut pointer = |cell: isize, lines: &mut Vec<String>| match cell - start {
+            0 => "%sp".to_string(),
+            offset => {
+                let name = format!("%p{}", cell);
+                if pointers.insert(cell) {
+                    lines.push(format!(
+                        "{} = getelementptr {}, ptr %sp, i64 {}",
+                        name, ty, offset
+                    ));
+                }
+                name
+            }
+        };
+
+        // Only the cells on the new stack that don't already hold their value are stored
+        let empty = Value::new();
+        let changed: Vec<(isize, &Value)> = (0..=top)
+            .map(|cell| (cell, self.cells.get(cell).unwrap_or(&empty)))
+            .filter(|&(cell, value)| cell > start || *value != Value::from([(cell, 1)]))
+            .collect();
+
+        let inputs: BTreeSet<isize> = changed
+            .iter()
+            .flat_map(|(_, value)| value.keys().copied())
+            .collect();
+        for &input in &inputs {
+            let pointer = pointer(input, &mut lines);
+            lines.push(format!("%v{} = load {}, ptr {}", input, ty, pointer));
+        }
+
+        let mut temps = 0;
+        for (cell, value) in changed {
+            let mut sum: Option<String> = None;
+            for (&input, &weight) in value {
+                let term = match weight {
+                    1 => format!("%v{}", input),
+                    weight => {
+                        lines.push(format!("%t{} = mul {} %v{}, {}", temps, ty, input, weight));
+                        temps += 1;
+                        format!("%t{}", temps - 1)
+                    }
+                };
+                sum = Some(match sum {
+                    None => term,
+                    Some(sum) => {
+                        lines.push(format!("%t{} = add {} {}, {}", temps, ty, sum, term));
+                        temps += 1;
+                        format!("%t{}", temps - 1)
+                    }
+                });
+            }
+
+            let pointer = pointer(cell, &mut lines);
+            let sum = sum.unwrap_or_else(|| "0".to_string());
+            lines.push(format!("store {} {}, ptr {}", ty, sum, pointer));
+        }
+
+        let result = match top - start {
+            0 => "%sp".to_string(),
+            shift => {
+                lines.push(format!(
+                    "%top = getelementptr {}, ptr %sp, i64 {}",
+                    ty, shift
+                ));
+                "%top".to_string()
+            }
+        };
+        lines.push(format!("ret ptr {}", result));
+
+        let mut function = format!("define ptr @{}(ptr %sp) {{\nentry:\n", self.name);
+        for line in lines {
+            function += &format!("  {}\n", line);
+        }
+        function + "}\n"
+    }
+}
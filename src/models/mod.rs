@@ -0,0 +1,23 @@
+//! Predefined models for certain programming languages
+
+mod assign;
+mod brainfuck;
+mod c;
+mod execute;
+mod listing;
+mod llvm;
+mod rust;
+mod sums;
+mod trace;
+mod wat;
+mod x86_64;
+
+pub use brainfuck::{Brainfuck, BrainfuckCost, BrainfuckOptions, ClearLoop, PointerPosition};
+pub use c::C;
+pub use execute::Execute;
+pub use listing::Listing;
+pub use llvm::LlvmIr;
+pub use rust::Rust;
+pub use trace::Trace;
+pub use wat::Wat;
+pub use x86_64::{Syntax, X86_64};
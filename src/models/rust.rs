@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use crate::Model;
+
+/// Where the generated Rust code finds the stack
+#[derive(Debug, Clone)]
+enum Target {
+    /// A `Vec<T>` whose last item is the top of the stack
+    Vec(String),
+    /// A `[T]` slice with a `usize` variable holding the index of the top of the stack
+    Slice { slice: String, top: String },
+}
+
+/// Emits Rust code that applies a diagram to a `Vec<T>` or a slice of `T: Clone` values.
+///
+/// Rather than translating every move, the model works out where each value ends up and then
+/// rearranges the stack with `swap`, copies duplicated values with `clone` and removes dropped
+/// values with `truncate`.
+///
+/// The model expects the instructions of a whole diagram solved with the default
+/// [`Layout`](crate::Layout), from `Start` to `Top`. Generating the program panics without them,
+/// or for instructions that add values together.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::autoperm;
+/// use autoperm::models::Rust;
+///
+/// let code = autoperm("a b c -- c a b", Rust::new()).unwrap();
+///
+/// assert_eq!(code, "\
+/// {
+///     let base = v.len() - 3;
+///     v.swap(base, base + 1);
+///     v.swap(base, base + 2);
+/// }
+/// ");
+///
+/// let code = autoperm("a b -- b a b", Rust::slice("stack", "top")).unwrap();
+///
+/// assert_eq!(code, "\
+/// stack.swap(top - 1, top);
+/// stack[top + 1] = stack[top - 1].clone();
+/// top += 1;
+/// ");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Rust {
+    target: Target,
+    /// The input each cell holds, if any
+    cells: HashMap<isize, usize>,
+    start: Option<isize>,
+    top: Option<isize>,
+}
+
+impl Rust {
+    /// Creates a new model for a `Vec` named `v`
+    pub fn new() -> Self {
+        Self::vec("v")
+    }
+
+    /// Creates a new model for a `Vec` whose last item is the top of the stack
+    pub fn vec(name: &str) -> Self {
+        Self::with_target(Target::Vec(name.to_string()))
+    }
+
+    /// Creates a new model for a slice with a `usize` variable holding the index of the top of the
+    /// stack. The slice must be long enough for the new top of the stack.
+    pub fn slice(slice: &str, top: &str) -> Self {
+        Self::with_target(Target::Slice {
+            slice: slice.to_string(),
+            top: top.to_string(),
+        })
+    }
+
+    fn with_target(target: Target) -> Self {
+        Self {
+            target,
+            cells: HashMap::new(),
+            start: None,
+            top: None,
+        }
+    }
+
+    fn name(&self) -> &str {
+        match &self.target {
+            Target::Vec(name) => name,
+            Target::Slice { slice, .. } => slice,
+        }
+    }
+
+    /// Returns the index expression of a position on the stack
+    fn index(&self, position: usize, inputs: usize) -> String {
+        match &self.target {
+            Target::Vec(_) => match position {
+                0 => "base".to_string(),
+                position => format!("base + {}", position),
+            },
+            Target::Slice { top, .. } => {
+                let offset = position as isize - (inputs as isize - 1);
+                match offset {
+                    0 => top.clone(),
+                    offset if offset < 0 => format!("{} - {}", top, -offset),
+                    offset => format!("{} + {}", top, offset),
+                }
+            }
+        }
+    }
+}
+
+impl Default for Rust {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Model for Rust {
+    type Output = String;
+
+    fn start(&mut self, cell: isize) {
+        self.start = Some(cell);
+        self.cells = (0..=cell).map(|cell| (cell, cell as usize)).collect();
+    }
+
+    fn clear(&mut self, cell: isize) {
+        self.cells.remove(&cell);
+    }
+
+    fn mov(&mut self, cell: isize, to: &[isize]) {
+        if let Some(value) = self.cells.remove(&cell) {
+            for &target in to {
+                let previous = self.cells.insert(target, value);
+                assert!(previous.is_none(), "cell {} is not empty", target);
+            }
+        }
+    }
+
+    fn top(&mut self, cell: isize) {
+        self.top = Some(cell);
+    }
+
+    fn finish(self) -> Self::Output {
+        let inputs = (self.start.expect("missing Start") + 1) as usize;
+        let outputs = (self.top.expect("missing Top") + 1) as usize;
+        let mapping: Vec<usize> = (0..outputs as isize)
+            .map(|cell| *self.cells.get(&cell).expect("an output is empty"))
+            .collect();
+
+        let name = self.name();
+        let index = |position| self.index(position, inputs);
+        let kept = std::cmp::min(inputs, outputs);
+
+        // Each input is moved to one of the kept positions that needs it, preferably where it is
+        let mut primary: Vec<Option<usize>> = vec![None; inputs];
+        for (position, &input) in mapping[..kept].iter().enumerate() {
+            if primary[input].is_none() || position == input {
+                primary[input] = Some(position);
+            }
+        }
+
+        let mut statements = Vec::new();
+
+        // Swap the moved inputs into place, every swap fixes one position
+        let mut held: Vec<usize> = (0..inputs).collect();
+        let mut at: Vec<usize> = (0..inputs).collect();
+        for (input, position) in primary.iter().enumerate() {
+            let Some(position) = *position else {
+                continue;
+            };
+            if at[input] != position {
+                let from = at[input];
+                statements.push(format!(
+                    "{}.swap({}, {});",
+                    name,
+                    index(from),
+                    index(position)
+                ));
+                held.swap(from, position);
+                at[held[from]] = from;
+                at[held[position]] = position;
+            }
+        }
+
+        // Copy the remaining outputs, new positions first so nothing they need is overwritten
+        let source = |input: usize| index(primary[input].unwrap_or(at[input]));
+        for (position, &input) in mapping.iter().enumerate().skip(kept) {
+            match &self.target {
+                Target::Vec(_) => {
+                    statements.push(format!("{0}.push({0}[{1}].clone());", name, source(input)))
+                }
+                Target::Slice { .. } => statements.push(format!(
+                    "{0}[{1}] = {0}[{2}].clone();",
+                    name,
+                    index(position),
+                    source(input)
+                )),
+            }
+        }
+        for (position, &input) in mapping[..kept].iter().enumerate() {
+            if primary[input] != Some(position) {
+                statements.push(format!(
+                    "{0}[{1}] = {0}[{2}].clone();",
+                    name,
+                    index(position),
+                    source(input)
+                ));
+            }
+        }
+
+        match &self.target {
+            Target::Vec(_) => {
+                if outputs < inputs {
+                    statements.push(format!("{}.truncate({});", name, index(outputs)));
+                }
+
+                if statements.is_empty() {
+                    return String::new();
+                }
+
+                let base = match inputs {
+                    0 => format!("let base = {}.len();", name),
+                    inputs => format!("let base = {}.len() - {};", name, inputs),
+                };
+                let mut code = format!("{{\n    {}\n", base);
+                for statement in statements {
+                    code += &format!("    {}\n", statement);
+                }
+                code + "}\n"
+            }
+            Target::Slice { top, .. } => {
+                match outputs as isize - inputs as isize {
+                    0 => {}
+                    shift if shift < 0 => statements.push(format!("{} -= {};", top, -shift)),
+                    shift => statements.push(format!("{} += {};", top, shift)),
+                }
+
+                statements.into_iter().map(|s| s + "\n").collect()
+            }
+        }
+    }
+}
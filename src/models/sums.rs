@@ -0,0 +1,55 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::Layout;
+
+/// A sum of inputs multiplied by weights, keyed by the cell each input started in
+pub(super) type Value = BTreeMap<isize, isize>;
+
+/// Follows which sum of the inputs each cell holds as a program runs
+#[derive(Debug, Clone, Default)]
+pub(super) struct Sums {
+    /// The value each cell holds, empty cells are left out
+    cells: HashMap<isize, Value>,
+}
+
+impl Sums {
+    /// The cells of the stack up to `top` each hold their own input
+    pub(super) fn start(layout: &Layout, top: isize) -> Self {
+        Self {
+            cells: (0..=layout.position(top))
+                .map(|position| layout.cell(position))
+                .map(|cell| (cell, Value::from([(cell, 1)])))
+                .collect(),
+        }
+    }
+
+    pub(super) fn clear(&mut self, cell: isize) {
+        self.cells.remove(&cell);
+    }
+
+    /// Empties a cell and adds its value times each weight to the targets
+    pub(super) fn mov(&mut self, cell: isize, to: &[(isize, isize)]) {
+        let Some(value) = self.cells.remove(&cell) else {
+            return;
+        };
+
+        for &(target, weight) in to {
+            let sum = self.cells.entry(target).or_default();
+            for (&input, &w) in &value {
+                *sum.entry(input).or_default() += w * weight;
+            }
+            sum.retain(|_, w| *w != 0);
+        }
+        self.cells.retain(|_, value| !value.is_empty());
+    }
+
+    /// The value a cell holds, or `None` when it is empty
+    pub(super) fn get(&self, cell: isize) -> Option<&Value> {
+        self.cells.get(&cell)
+    }
+
+    /// The cells that hold a value
+    pub(super) fn cells(&self) -> impl Iterator<Item = isize> + '_ {
+        self.cells.keys().copied()
+    }
+}
@@ -0,0 +1,275 @@
+use itertools::Itertools;
+
+use crate::{Layout, Model};
+
+use super::{
+    sums::{Sums, Value},
+    Brainfuck,
+};
+
+/// What a step of the program does
+#[derive(Debug, Clone)]
+enum Action {
+    Start,
+    Clear(isize),
+    Mov(isize, Vec<(isize, isize)>),
+    Top,
+}
+
+/// The tape after a step of the program
+#[derive(Debug, Clone)]
+struct Step {
+    /// The Brainfuck code of the step
+    code: String,
+    action: Action,
+    pointer: isize,
+    cells: Sums,
+}
+
+/// Generates Brainfuck like [`Brainfuck`] and explains it step by step.
+///
+/// The trace starts with the whole program, followed by the tape before the program runs. Every
+/// instruction is then shown as the Brainfuck it generates, a description of the move, and the
+/// tape afterwards with the pointer marked by a `*`. Inputs are named `a`, `b`, `c`, ... unless
+/// [`names`](Trace::names) are given, and temporary cells past the stack are labeled `T`. The
+/// [`layout`](Trace::layout) the diagram was solved with tells where those cells are.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::autoperm;
+/// use autoperm::models::Trace;
+///
+/// let trace = autoperm("x y -- y", Trace::new().names(&["x", "y"])).unwrap();
+///
+/// assert_eq!(trace, "\
+/// <[-]>[-<+>]<
+///
+/// ## the tape
+///  0 *1
+///  x  y
+///
+/// <[-]     clear 0
+/// *0  1
+///  0  y
+///
+/// >[-<+>]  1 → {0}
+///  0 *1
+///  y  0
+///
+/// <
+/// *0  1
+///  y  0
+/// ");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    brainfuck: Brainfuck,
+    names: Vec<String>,
+    layout: Layout,
+    start: Option<isize>,
+    top: Option<isize>,
+    cells: Sums,
+    initial: Option<Step>,
+    steps: Vec<Step>,
+}
+
+impl Trace {
+    /// Creates a new model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the names of the inputs, from the bottom of the stack up
+    pub fn names<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.names = names.iter().map(|name| name.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Sets the [`Layout`](crate::Layout) the diagram was solved with, which decides where the
+    /// inputs and the temporary cells are
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    fn name(&self, input: usize) -> String {
+        match self.names.get(input) {
+            Some(name) => name.clone(),
+            None if input < 26 => ((b'a' + input as u8) as char).to_string(),
+            None => format!("x{}", input),
+        }
+    }
+
+    /// Records the code generated since the last step
+    fn step(&mut self, action: Action, pointer: isize) {
+        let before: usize = self.steps.iter().map(|step| step.code.len()).sum();
+        self.steps.push(Step {
+            code: self.brainfuck.program()[before..].to_string(),
+            action,
+            pointer,
+            cells: self.cells.clone(),
+        });
+    }
+
+    /// Renders a value as a sum of the names of inputs
+    fn value(&self, value: Option<&Value>) -> String {
+        let Some(value) = value.filter(|value| !value.is_empty()) else {
+            return "0".to_string();
+        };
+
+        let mut sum = String::new();
+        for (&input, &weight) in value {
+            let name = self.name(self.layout.position(input) as usize);
+            sum += &match weight {
+                1 if sum.is_empty() => name,
+                1 => format!("+{}", name),
+                -1 => format!("-{}", name),
+                weight if weight > 0 && !sum.is_empty() => format!("+{}*{}", weight, name),
+                weight => format!("{}*{}", weight, name),
+            };
+        }
+        sum
+    }
+}
+
+impl Model for Trace {
+    type Output = String;
+
+    fn start(&mut self, cell: isize) {
+        self.brainfuck.start(cell);
+        self.start = Some(cell);
+        self.cells = Sums::start(&self.layout, cell);
+        self.initial = Some(Step {
+            code: String::new(),
+            action: Action::Start,
+            pointer: cell,
+            cells: self.cells.clone(),
+        });
+    }
+
+    fn clear(&mut self, cell: isize) {
+        self.brainfuck.clear(cell);
+        self.cells.clear(cell);
+        self.step(Action::Clear(cell), cell);
+    }
+
+    fn mov(&mut self, cell: isize, to: &[isize]) {
+        self.brainfuck.mov(cell, to);
+        let to: Vec<(isize, isize)> = to.iter().map(|&target| (target, 1)).collect();
+        self.cells.mov(cell, &to);
+        self.step(Action::Mov(cell, to), cell);
+    }
+
+    fn weighted_mov(&mut self, cell: isize, to: &[(isize, isize)]) {
+        self.brainfuck.weighted_mov(cell, to);
+        self.cells.mov(cell, to);
+        self.step(Action::Mov(cell, to.to_vec()), cell);
+    }
+
+    fn supports_weights(&self) -> bool {
+        true
+    }
+
+    fn top(&mut self, cell: isize) {
+        self.brainfuck.top(cell);
+        self.top = Some(cell);
+        self.step(Action::Top, cell);
+    }
+
+    fn finish(self) -> Self::Output {
+        let scratch = self.layout.scratch(self.start, self.top);
+        let label = |cell: isize| match scratch.and_then(|scratch| scratch.index(cell)) {
+            Some(0) => "T".to_string(),
+            Some(n) => format!("T{}", n),
+            None => cell.to_string(),
+        };
+
+        // Every cell that is used, with room for the labels and values of every step
+        let steps: Vec<&Step> = self.initial.iter().chain(&self.steps).collect();
+        let used = steps
+            .iter()
+            .flat_map(|step| step.cells.cells().chain([step.pointer]))
+            .chain([0]);
+        let (low, high) = match used.minmax().into_option() {
+            Some((low, high)) => (low, high),
+            None => (0, 0),
+        };
+        // The tape reaches up to the temporary cells, or down to them on a downward stack
+        let (low, high) = match scratch.map(|scratch| scratch.cell(-1)) {
+            Some(edge) => (std::cmp::min(low, edge), std::cmp::max(high, edge)),
+            None => (low, high),
+        };
+        let rows: Vec<Vec<String>> = steps
+            .iter()
+            .map(|step| {
+                (low..=high)
+                    .map(|cell| self.value(step.cells.get(cell)))
+                    .collect()
+            })
+            .collect();
+        let width = (low..=high)
+            .map(|cell| {
+                let index = (cell - low) as usize;
+                rows.iter()
+                    .map(|values| values[index].chars().count())
+                    .chain([label(cell).chars().count()])
+                    .max()
+                    .unwrap()
+            })
+            .max()
+            .unwrap_or(1);
+
+        let tape = |step: &Step, values: &[String]| {
+            let labels = (low..=high)
+                .map(|cell| {
+                    let marker = if cell == step.pointer { '*' } else { ' ' };
+                    format!("{}{:>width$}", marker, label(cell), width = width)
+                })
+                .join(" ");
+            let values = values
+                .iter()
+                .map(|value| format!(" {:>width$}", value, width = width))
+                .join(" ");
+            format!("{}\n{}\n", labels.trim_end(), values.trim_end())
+        };
+
+        let program = self.brainfuck.program().to_string();
+        let mut trace = format!("{}\n\n# the tape\n", program);
+
+        let code = self
+            .steps
+            .iter()
+            .map(|step| step.code.len())
+            .max()
+            .unwrap_or(0);
+        for (step, values) in steps.iter().zip(&rows) {
+            let description = match &step.action {
+                Action::Start => {
+                    trace += &tape(step, values);
+                    continue;
+                }
+                // Moving to the top of the stack is only shown when the pointer moves
+                Action::Top if step.code.is_empty() => continue,
+                Action::Top => String::new(),
+                Action::Clear(cell) => format!("clear {}", label(*cell)),
+                Action::Mov(cell, to) => {
+                    let targets = to
+                        .iter()
+                        .map(|&(target, weight)| match weight {
+                            1 => label(target),
+                            weight => format!("{}*{}", label(target), weight),
+                        })
+                        .join(" ");
+                    format!("{} → {{{}}}", label(*cell), targets)
+                }
+            };
+
+            let line = format!("{:code$}  {}", step.code, description, code = code);
+            trace += &format!("\n{}\n", line.trim_end());
+            trace += &tape(step, values);
+        }
+
+        trace
+    }
+}
@@ -0,0 +1,316 @@
+use std::{cell::Cell, collections::BTreeSet};
+
+use crate::{layout::Scratch, Layout, Model};
+
+use super::assign::{Assign, Assignments, Write};
+
+/// Emits a WebAssembly text function body that applies a diagram to a stack of `i32` values in
+/// linear memory.
+///
+/// The stack is addressed through an `i32` local, `$sp` by default, holding the address of the top
+/// of the stack, and `$sp` is moved to the new top at the end. Each cell is one `i32`, so by
+/// default the stack grows toward higher addresses, and a diagram solved for another
+/// [`layout`](Wat::layout) may grow toward lower ones. Memory is accessed with `i32.load` and
+/// `i32.store` offsets from the lowest cell of the stack, so a `$base` local is declared when
+/// that is not the top. The temporary cell past the stack becomes a local, and a cell that still
+/// holds a value is added to with `i32.add`.
+///
+/// With [`module`](Wat::module) the body is wrapped in a complete module that exports its memory
+/// and a function taking and returning `$sp`.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::autoperm;
+/// use autoperm::models::Wat;
+///
+/// let body = autoperm("a b -- b a", Wat::new()).unwrap();
+///
+/// assert_eq!(body, "\
+/// (local $base i32)
+/// (local $tmp i32)
+/// (local.set $base (i32.sub (local.get $sp) (i32.const 4)))
+/// (local.set $tmp (i32.load offset=4 (local.get $base)))
+/// (i32.store offset=4 (local.get $base) (i32.load (local.get $base)))
+/// (i32.store (local.get $base) (local.get $tmp))
+/// ");
+///
+/// let module = autoperm("a -- a a", Wat::new().module("dup")).unwrap();
+///
+/// assert_eq!(module, r#"(module
+///   (memory (export "memory") 1)
+///   (func (export "dup") (param $sp i32) (result i32)
+///     (local $tmp i32)
+///     (local.set $tmp (i32.load (local.get $sp)))
+///     (i32.store offset=4 (local.get $sp) (local.get $tmp))
+///     (i32.store (local.get $sp) (local.get $tmp))
+///     (local.set $sp (i32.add (local.get $sp) (i32.const 4)))
+///     (local.get $sp)
+///   )
+/// )
+/// "#);
+/// ```
+///
+/// A stack that grows toward lower addresses is solved and emitted with the same layout:
+///
+/// ```
+/// use autoperm::{generate, parse, solve_with, Layout, SolveOptions};
+/// use autoperm::models::Wat;
+///
+/// let layout = Layout::downward();
+/// let instructions = solve_with(&parse("a b -- b a").unwrap(), &SolveOptions::new().layout(layout));
+///
+/// assert_eq!(generate(instructions, Wat::new().layout(layout)), "\
+/// (local $tmp i32)
+/// (local.set $tmp (i32.load (local.get $sp)))
+/// (i32.store (local.get $sp) (i32.load offset=4 (local.get $sp)))
+/// (i32.store offset=4 (local.get $sp) (local.get $tmp))
+/// ");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Wat {
+    pointer: String,
+    temp: String,
+    module: Option<String>,
+    layout: Layout,
+    program: Assignments,
+}
+
+impl Wat {
+    /// Creates a new model using a `$sp` local
+    pub fn new() -> Self {
+        Self {
+            pointer: "sp".to_string(),
+            temp: "tmp".to_string(),
+            module: None,
+            layout: Layout::upward(),
+            program: Assignments::default(),
+        }
+    }
+
+    /// Sets the name of the local holding the address of the top of the stack, without the `$`
+    pub fn pointer(mut self, pointer: &str) -> Self {
+        self.pointer = pointer.to_string();
+        self
+    }
+
+    /// Sets the name of the temporary local, without the `$`
+    pub fn temp(mut self, temp: &str) -> Self {
+        self.temp = temp.to_string();
+        self
+    }
+
+    /// Emits a complete module that exports the function with the given name
+    pub fn module(mut self, export: &str) -> Self {
+        self.module = Some(export.to_string());
+        self
+    }
+
+    /// Sets the [`Layout`](crate::Layout) the instructions were solved with, which decides which
+    /// cells are locals and which cells of the stack start out holding a value
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Returns the name of the local for the `n`th temporary cell
+    fn local(&self, n: isize) -> String {
+        match n {
+            0 => format!("${}", self.temp),
+            n => format!("${}{}", self.temp, n),
+        }
+    }
+}
+
+impl Default for Wat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Where the cells of the program live
+struct Cells<'a> {
+    model: &'a Wat,
+    base: String,
+    /// The cell at the address in `base`
+    low: isize,
+    scratch: Option<Scratch>,
+    /// Whether any cell in memory has been addressed
+    addressed: Cell<bool>,
+}
+
+impl Cells<'_> {
+    /// Returns which temporary cell a cell is, or `None` for a cell in memory
+    fn temp(&self, cell: isize) -> Option<isize> {
+        self.scratch.and_then(|scratch| scratch.index(cell))
+    }
+
+    /// Returns an instruction that reads a cell
+    fn get(&self, cell: isize) -> String {
+        if let Some(n) = self.temp(cell) {
+            return format!("(local.get {})", self.model.local(n));
+        }
+        self.addressed.set(true);
+
+        match (cell - self.low) * 4 {
+            0 => format!("(i32.load (local.get {}))", self.base),
+            offset => format!("(i32.load offset={} (local.get {}))", offset, self.base),
+        }
+    }
+
+    /// Returns an instruction that writes a value to a cell
+    fn set(&self, cell: isize, value: &str) -> String {
+        if let Some(n) = self.temp(cell) {
+            return format!("(local.set {} {})", self.model.local(n), value);
+        }
+        self.addressed.set(true);
+
+        match (cell - self.low) * 4 {
+            0 => format!("(i32.store (local.get {}) {})", self.base, value),
+            offset => format!(
+                "(i32.store offset={} (local.get {}) {})",
+                offset, self.base, value
+            ),
+        }
+    }
+}
+
+/// The body of a [`Wat`] function before its locals are declared
+struct Body<'a> {
+    cells: Cells<'a>,
+    pointer: String,
+    /// The temporary cells that are used, by their index
+    locals: BTreeSet<isize>,
+    lines: Vec<String>,
+}
+
+impl Assign for Body<'_> {
+    fn mov(&mut self, cell: isize, writes: &[Write]) {
+        for write in writes {
+            let value = match write.weight {
+                1 => self.cells.get(cell),
+                weight => format!("(i32.mul {} (i32.const {}))", self.cells.get(cell), weight),
+            };
+            let value = if write.add {
+                format!("(i32.add {} {})", self.cells.get(write.target), value)
+            } else {
+                value
+            };
+            self.lines.push(self.cells.set(write.target, &value));
+
+            if let Some(n) = self.cells.temp(write.target) {
+                self.locals.insert(n);
+            }
+        }
+    }
+
+    fn zero(&mut self, cell: isize) {
+        self.lines.push(self.cells.set(cell, "(i32.const 0)"));
+    }
+
+    fn shift(&mut self, cells: isize) {
+        let (operation, bytes) = if cells < 0 {
+            ("sub", -cells * 4)
+        } else {
+            ("add", cells * 4)
+        };
+        self.lines.push(format!(
+            "(local.set {0} (i32.{1} (local.get {0}) (i32.const {2})))",
+            self.pointer, operation, bytes
+        ));
+    }
+}
+
+impl Model for Wat {
+    type Output = String;
+
+    fn start(&mut self, cell: isize) {
+        self.program.start(cell);
+    }
+
+    fn clear(&mut self, cell: isize) {
+        self.program.clear(cell);
+    }
+
+    fn mov(&mut self, cell: isize, to: &[isize]) {
+        let to: Vec<(isize, isize)> = to.iter().map(|&target| (target, 1)).collect();
+        self.program.mov(cell, &to);
+    }
+
+    fn weighted_mov(&mut self, cell: isize, to: &[(isize, isize)]) {
+        self.program.mov(cell, to);
+    }
+
+    fn supports_weights(&self) -> bool {
+        true
+    }
+
+    fn top(&mut self, cell: isize) {
+        self.program.top(cell);
+    }
+
+    fn finish(self) -> Self::Output {
+        let start = self.program.start_cell();
+        let top = self.program.top_cell().unwrap_or(start);
+        // Offsets are never negative, so memory is addressed from the lowest cell of the stack
+        let highest = std::cmp::max(self.layout.position(start), self.layout.position(top));
+        let low = std::cmp::min(self.layout.cell(0), self.layout.cell(highest));
+        let pointer = format!("${}", self.pointer);
+        let mut body = Body {
+            cells: Cells {
+                model: &self,
+                base: match start - low {
+                    0 => pointer.clone(),
+                    _ => "$base".to_string(),
+                },
+                low,
+                scratch: self.program.scratch(&self.layout),
+                addressed: Cell::new(false),
+            },
+            pointer: pointer.clone(),
+            locals: BTreeSet::new(),
+            lines: Vec::new(),
+        };
+        self.program.render(&self.layout, &mut body);
+        let Body {
+            cells,
+            locals,
+            lines: body,
+            ..
+        } = body;
+
+        // Locals are declared before any instruction
+        let based = start != low && cells.addressed.get();
+        let mut lines = Vec::new();
+        if based {
+            lines.push("(local $base i32)".to_string());
+        }
+        for &n in &locals {
+            lines.push(format!("(local {} i32)", self.local(n)));
+        }
+        if based {
+            lines.push(format!(
+                "(local.set $base (i32.sub (local.get {}) (i32.const {})))",
+                pointer,
+                (start - low) * 4
+            ));
+        }
+        lines.extend(body);
+
+        match &self.module {
+            None => lines.into_iter().map(|line| line + "\n").collect(),
+            Some(export) => {
+                let mut module = format!(
+                    "(module\n  (memory (export \"memory\") 1)\n  \
+                     (func (export \"{}\") (param {} i32) (result i32)\n",
+                    export, pointer
+                );
+                for line in lines {
+                    module += &format!("    {}\n", line);
+                }
+                module += &format!("    (local.get {})\n  )\n)\n", pointer);
+                module
+            }
+        }
+    }
+}
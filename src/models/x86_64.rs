@@ -0,0 +1,310 @@
+use crate::{layout::Scratch, Layout, Model};
+
+use super::assign::{Assign, Assignments, Write};
+
+/// The assembler syntax emitted by [`X86_64`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
+pub enum Syntax {
+    /// Intel syntax as read by NASM, `mov rax, qword [rbx + 8]`
+    #[default]
+    Nasm,
+    /// AT&T syntax as read by the GNU assembler, `movq 8(%rbx), %rax`
+    Gas,
+}
+
+/// An operand of an instruction
+#[derive(Debug, Clone, Copy)]
+enum Operand<'a> {
+    Register(&'a str),
+    /// A word at a byte offset from the base register
+    Memory(isize),
+    Immediate(isize),
+}
+
+/// Registers that may hold the temporary cell
+const SCRATCH: [&str; 7] = ["rcx", "rsi", "rdi", "r8", "r9", "r10", "r11"];
+
+/// Emits x86-64 assembly that applies a diagram to a stack of 64-bit words in memory.
+///
+/// The base register points at the top of the stack and is moved to the new top at the end. Each
+/// cell is one word, so by default the stack grows toward higher addresses, and a diagram solved
+/// for another [`layout`](X86_64::layout) may grow toward lower ones. Values are moved through
+/// `rax` (and `rdx` for weighted moves), and the temporary cell past the stack is kept in `rcx`
+/// instead of memory. A cycle of two cells is swapped with `xchg`, and dropped cells left on the new stack
+/// are stored as an immediate 0.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::autoperm;
+/// use autoperm::models::{Syntax, X86_64};
+///
+/// let code = autoperm("a b -- b a", X86_64::new()).unwrap();
+///
+/// assert_eq!(code, "\
+/// mov rax, qword [rbx]
+/// xchg rax, qword [rbx - 8]
+/// mov qword [rbx], rax
+/// ");
+///
+/// let code = autoperm("a b c -- b c a", X86_64::new().syntax(Syntax::Gas)).unwrap();
+///
+/// assert_eq!(code, "\
+/// movq -8(%rbx), %rcx
+/// movq (%rbx), %rax
+/// movq %rax, -8(%rbx)
+/// movq -16(%rbx), %rax
+/// movq %rax, (%rbx)
+/// movq %rcx, -16(%rbx)
+/// ");
+/// ```
+///
+/// A stack that grows toward lower addresses is solved and emitted with the same layout:
+///
+/// ```
+/// use autoperm::{generate, parse, solve_with, Layout, SolveOptions};
+/// use autoperm::models::X86_64;
+///
+/// let layout = Layout::downward();
+/// let instructions = solve_with(&parse("a b c -- b c a").unwrap(), &SolveOptions::new().layout(layout));
+///
+/// assert_eq!(generate(instructions, X86_64::new().layout(layout)), "\
+/// mov rcx, qword [rbx + 8]
+/// mov rax, qword [rbx]
+/// mov qword [rbx + 8], rax
+/// mov rax, qword [rbx + 16]
+/// mov qword [rbx], rax
+/// mov qword [rbx + 16], rcx
+/// ");
+/// ```
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+pub struct X86_64 {
+    syntax: Syntax,
+    base: String,
+    layout: Layout,
+    program: Assignments,
+}
+
+impl X86_64 {
+    /// Creates a new model using `rbx` as the base register
+    pub fn new() -> Self {
+        Self {
+            syntax: Syntax::Nasm,
+            base: "rbx".to_string(),
+            layout: Layout::upward(),
+            program: Assignments::default(),
+        }
+    }
+
+    /// Sets the assembler syntax
+    pub fn syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Sets the register that points at the top of the stack
+    ///
+    /// # Panics
+    ///
+    /// Panics if the register is `rax` or `rdx`, which are used to move values
+    pub fn base(mut self, register: &str) -> Self {
+        assert!(
+            !["rax", "rdx"].contains(&register),
+            "{} is used to move values",
+            register
+        );
+        self.base = register.to_string();
+        self
+    }
+
+    /// Sets the [`Layout`](crate::Layout) the instructions were solved with, which decides which
+    /// cells are kept in registers and which cells of the stack start out holding a value
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    fn operand(&self, operand: Operand) -> String {
+        match (self.syntax, operand) {
+            (Syntax::Nasm, Operand::Register(register)) => register.to_string(),
+            (Syntax::Nasm, Operand::Memory(0)) => format!("qword [{}]", self.base),
+            (Syntax::Nasm, Operand::Memory(offset)) if offset < 0 => {
+                format!("qword [{} - {}]", self.base, -offset)
+            }
+            (Syntax::Nasm, Operand::Memory(offset)) => {
+                format!("qword [{} + {}]", self.base, offset)
+            }
+            (Syntax::Nasm, Operand::Immediate(value)) => value.to_string(),
+            (Syntax::Gas, Operand::Register(register)) => format!("%{}", register),
+            (Syntax::Gas, Operand::Memory(0)) => format!("(%{})", self.base),
+            (Syntax::Gas, Operand::Memory(offset)) => format!("{}(%{})", offset, self.base),
+            (Syntax::Gas, Operand::Immediate(value)) => format!("${}", value),
+        }
+    }
+
+    /// Formats an instruction with the destination first, as in Intel syntax
+    fn emit(&self, program: &mut String, mnemonic: &str, operands: &[Operand]) {
+        let mut operands: Vec<String> = operands.iter().map(|&o| self.operand(o)).collect();
+        match self.syntax {
+            Syntax::Nasm => *program += &format!("{} {}\n", mnemonic, operands.join(", ")),
+            Syntax::Gas => {
+                operands.reverse();
+                *program += &format!("{}q {}\n", mnemonic, operands.join(", "))
+            }
+        }
+    }
+}
+
+impl Default for X86_64 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Where the cells of the program live
+struct Cells<'a> {
+    start: isize,
+    scratch: Option<Scratch>,
+    registers: Vec<&'a str>,
+}
+
+impl<'a> Cells<'a> {
+    /// Returns which temporary cell a cell is, or `None` for a cell in memory
+    fn temp(&self, cell: isize) -> Option<isize> {
+        self.scratch.and_then(|scratch| scratch.index(cell))
+    }
+
+    fn operand(&self, cell: isize) -> Operand<'a> {
+        if let Some(n) = self.temp(cell) {
+            let register = self.registers.get(n as usize);
+            return Operand::Register(register.expect("too many temporary cells"));
+        }
+        Operand::Memory((cell - self.start) * 8)
+    }
+}
+
+/// The code of an [`X86_64`] program
+struct Code<'a> {
+    model: &'a X86_64,
+    cells: Cells<'a>,
+    program: String,
+}
+
+impl Code<'_> {
+    fn emit(&mut self, mnemonic: &str, operands: &[Operand]) {
+        self.model.emit(&mut self.program, mnemonic, operands);
+    }
+}
+
+impl Assign for Code<'_> {
+    fn mov(&mut self, cell: isize, writes: &[Write]) {
+        let rax = Operand::Register("rax");
+        let rdx = Operand::Register("rdx");
+
+        // A single value moved from the stack into an empty register can be loaded directly
+        if let [Write {
+            target,
+            weight: 1,
+            add: false,
+        }] = writes
+        {
+            if self.cells.temp(cell).is_none() && self.cells.temp(*target).is_some() {
+                let target = self.cells.operand(*target);
+                let source = self.cells.operand(cell);
+                self.emit("mov", &[target, source]);
+                return;
+            }
+        }
+
+        // Registers can be used directly, memory has to go through rax
+        let source = match self.cells.operand(cell) {
+            Operand::Memory(offset) => {
+                self.emit("mov", &[rax, Operand::Memory(offset)]);
+                rax
+            }
+            register => register,
+        };
+
+        for write in writes {
+            let value = match write.weight {
+                1 => source,
+                weight => {
+                    self.emit("imul", &[rdx, source, Operand::Immediate(weight)]);
+                    rdx
+                }
+            };
+
+            let mnemonic = if write.add { "add" } else { "mov" };
+            let target = self.cells.operand(write.target);
+            self.emit(mnemonic, &[target, value]);
+        }
+    }
+
+    fn swap(&mut self, a: isize, b: isize) -> bool {
+        let rax = Operand::Register("rax");
+        let (a, b) = (self.cells.operand(a), self.cells.operand(b));
+        self.emit("mov", &[rax, a]);
+        self.emit("xchg", &[rax, b]);
+        self.emit("mov", &[a, rax]);
+        true
+    }
+
+    fn zero(&mut self, cell: isize) {
+        let cell = self.cells.operand(cell);
+        self.emit("mov", &[cell, Operand::Immediate(0)]);
+    }
+
+    fn shift(&mut self, cells: isize) {
+        let model = self.model;
+        let base = Operand::Register(&model.base);
+        if cells < 0 {
+            self.emit("sub", &[base, Operand::Immediate(-cells * 8)]);
+        } else {
+            self.emit("add", &[base, Operand::Immediate(cells * 8)]);
+        }
+    }
+}
+
+impl Model for X86_64 {
+    type Output = String;
+
+    fn start(&mut self, cell: isize) {
+        self.program.start(cell);
+    }
+
+    fn clear(&mut self, cell: isize) {
+        self.program.clear(cell);
+    }
+
+    fn mov(&mut self, cell: isize, to: &[isize]) {
+        let to: Vec<(isize, isize)> = to.iter().map(|&target| (target, 1)).collect();
+        self.program.mov(cell, &to);
+    }
+
+    fn weighted_mov(&mut self, cell: isize, to: &[(isize, isize)]) {
+        self.program.mov(cell, to);
+    }
+
+    fn supports_weights(&self) -> bool {
+        true
+    }
+
+    fn top(&mut self, cell: isize) {
+        self.program.top(cell);
+    }
+
+    fn finish(self) -> Self::Output {
+        let mut code = Code {
+            model: &self,
+            cells: Cells {
+                start: self.program.start_cell(),
+                scratch: self.program.scratch(&self.layout),
+                registers: SCRATCH.into_iter().filter(|r| *r != self.base).collect(),
+            },
+            program: String::new(),
+        };
+        self.program.render(&self.layout, &mut code);
+        code.program
+    }
+}
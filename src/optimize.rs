@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+
+use crate::Instruction;
+
+/// Removes redundant work from a list of instructions, such as the programs of several diagrams
+/// joined together.
+///
+/// - `Clear`s of cells that are already empty are dropped.
+/// - A `Mov` into a single empty cell that is immediately moved on is merged into one `Mov`.
+/// - Only the last of several `Top`s is kept, and a `Top` followed by a `Start` at the same cell
+///   is dropped along with the `Start`.
+///
+/// A cell is only known to be empty after it has been cleared or moved. A `Start` at a different
+/// cell than the `Top` before it begins a new program, so nothing is known about the tape after it.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::{optimize, parse, solve, Instruction};
+///
+/// // NIP followed by DUP
+/// let mut instructions = solve(&parse("a b -- b").unwrap());
+/// instructions.extend(solve(&parse("a -- a a").unwrap()));
+///
+/// assert_eq!(optimize(instructions), vec![
+///     Instruction::Start { cell: 1 },
+///     Instruction::Clear { cell: 0 },
+///     Instruction::Mov { cell: 1, to: vec![2] },
+///     Instruction::Mov { cell: 2, to: vec![1, 0] },
+///     Instruction::Top { cell: 1 },
+/// ]);
+/// ```
+pub fn optimize(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let mut optimized: Vec<Instruction> = Vec::with_capacity(instructions.len());
+
+    // Cells known to be empty
+    let mut zero = HashSet::new();
+    // Whether the last instruction moved a cell into a single cell that was empty before
+    let mut parked = false;
+    // The last `Top`, held back until it is known not to be redundant
+    let mut top = None;
+
+    for instruction in instructions {
+        match instruction {
+            Instruction::Clear { cell } => {
+                if zero.insert(cell) {
+                    optimized.push(Instruction::Clear { cell });
+                    parked = false;
+                }
+            }
+            Instruction::Mov { cell, to } => {
+                let empty = to.len() == 1 && to[0] != cell && zero.contains(&to[0]);
+
+                to.iter().for_each(|target| {
+                    zero.remove(target);
+                });
+                zero.insert(cell);
+
+                match optimized.last_mut() {
+                    // The cell in the middle is left empty either way
+                    Some(Instruction::Mov {
+                        cell: from,
+                        to: via,
+                    }) if parked && via[..] == [cell] && !to.contains(from) => *via = to,
+                    _ => optimized.push(Instruction::Mov { cell, to }),
+                }
+                parked = empty;
+            }
+            Instruction::WeightedMov { cell, to } => {
+                to.iter().for_each(|(target, _)| {
+                    zero.remove(target);
+                });
+                zero.insert(cell);
+                optimized.push(Instruction::WeightedMov { cell, to });
+                parked = false;
+            }
+            Instruction::Start { cell } => {
+                // The program carries on where the last one left the top of the stack
+                match top.take() {
+                    Some(top) if top == cell => continue,
+                    Some(top) => optimized.push(Instruction::Top { cell: top }),
+                    None => {}
+                }
+                optimized.push(Instruction::Start { cell });
+                zero.clear();
+                parked = false;
+            }
+            Instruction::Top { cell } => top = Some(cell),
+        }
+    }
+
+    if let Some(cell) = top {
+        optimized.push(Instruction::Top { cell });
+    }
+
+    optimized
+}
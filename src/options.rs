@@ -0,0 +1,149 @@
+use std::collections::BTreeSet;
+
+use itertools::Itertools;
+
+use crate::Layout;
+
+/// Describes what is already known about the tape before a program runs.
+///
+/// The default options describe the worst case that [`solve`](crate::solve) has always assumed:
+/// nothing is known about the inputs, every cell above the top of the stack starts at 0 and every
+/// cell above the new top of the stack must be left at 0.
+///
+/// # Example
+///
+/// ```
+/// use autoperm::{parse, solve_with, Instruction, SolveOptions};
+///
+/// // a b c -- c
+/// let diagram = parse("a b c -- c").unwrap();
+///
+/// // `a` was just consumed and the caller doesn't care what is left above the stack
+/// let options = SolveOptions::new().assume_zero(0).clear_dropped(false);
+///
+/// assert_eq!(solve_with(&diagram, &options), vec![
+///     Instruction::Start { cell: 2 },
+///     Instruction::Mov { cell: 2, to: vec![0] },
+///     Instruction::Top { cell: 0 },
+/// ]);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct SolveOptions {
+    zeroed: BTreeSet<isize>,
+    zero_above: bool,
+    clear_dropped: bool,
+    layout: Layout,
+}
+
+impl SolveOptions {
+    /// Creates the default options
+    pub fn new() -> Self {
+        Self {
+            zeroed: BTreeSet::new(),
+            zero_above: true,
+            clear_dropped: true,
+            layout: Layout::upward(),
+        }
+    }
+
+    /// Marks a cell as already being 0 before the program runs
+    pub fn assume_zero(mut self, cell: isize) -> Self {
+        self.zeroed.insert(cell);
+        self
+    }
+
+    /// Sets whether the cells above the top of the stack start at 0.
+    ///
+    /// When `false` any new cell, including the temporary cell, is cleared before it is used.
+    pub fn zero_above(mut self, zero_above: bool) -> Self {
+        self.zero_above = zero_above;
+        self
+    }
+
+    /// Sets whether dropped cells that end up above the new top of the stack must be cleared.
+    ///
+    /// Dropped cells that are overwritten by another value are always cleared first because a
+    /// `Mov` adds to its destinations.
+    pub fn clear_dropped(mut self, clear_dropped: bool) -> Self {
+        self.clear_dropped = clear_dropped;
+        self
+    }
+
+    /// Sets where the items of the stack are stored on the tape, see [`Layout`](crate::Layout).
+    ///
+    /// The cells given to [`assume_zero`](SolveOptions::assume_zero) are cells of the tape, not
+    /// positions in the stack.
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Returns true if the cell at a position of the stack is known to be 0 before the program
+    /// runs, given the number of inputs on the stack.
+    pub(crate) fn is_zero(&self, position: isize, inputs: usize) -> bool {
+        self.zeroed.contains(&self.cell(position))
+            || (self.zero_above && position >= inputs as isize)
+    }
+
+    /// Returns the cell that stores a position of the stack
+    pub(crate) fn cell(&self, position: isize) -> isize {
+        self.layout.cell(position)
+    }
+
+    /// Returns the temporary cell used when it would be stored at a position of the stack
+    pub(crate) fn temp(&self, position: isize) -> isize {
+        self.layout.temp(position).0
+    }
+
+    /// Returns true if the temporary cell is known to be 0 before the program runs
+    pub(crate) fn temp_is_zero(&self, position: isize, inputs: usize) -> bool {
+        match self.layout.temp(position) {
+            (cell, true) => self.zeroed.contains(&cell),
+            (_, false) => self.is_zero(position, inputs),
+        }
+    }
+
+    pub(crate) fn clears_dropped(&self) -> bool {
+        self.clear_dropped
+    }
+
+    /// Writes the options in the format read by [`decode`](SolveOptions::decode)
+    pub(crate) fn encode(&self) -> String {
+        format!(
+            "zeroed={} zero_above={} clear_dropped={} layout={}",
+            self.zeroed.iter().join(","),
+            self.zero_above,
+            self.clear_dropped,
+            self.layout.encode()
+        )
+    }
+
+    pub(crate) fn decode(s: &str) -> Option<Self> {
+        let mut fields = s.split(' ');
+        let mut field = |name: &str| fields.next()?.strip_prefix(name)?.strip_prefix('=');
+
+        let zeroed = match field("zeroed")? {
+            "" => BTreeSet::new(),
+            cells => cells
+                .split(',')
+                .map(|cell| cell.parse().ok())
+                .collect::<Option<_>>()?,
+        };
+        let zero_above = field("zero_above")?.parse().ok()?;
+        let clear_dropped = field("clear_dropped")?.parse().ok()?;
+        let layout = Layout::decode(field("layout")?)?;
+
+        fields.next().is_none().then_some(SolveOptions {
+            zeroed,
+            zero_above,
+            clear_dropped,
+            layout,
+        })
+    }
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -15,7 +15,7 @@ use std::collections::HashMap;
 /// });
 /// ```
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub struct StackEffectDiagram {
     /// Mapping of output symbols to their starting positions
     pub mapping: Vec<usize>,
@@ -25,6 +25,7 @@ pub struct StackEffectDiagram {
 
 /// Returned when [`parse`](crate::parse::parse) fails
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[non_exhaustive]
 pub enum ParseError {
     /// Returned where there is no "--" symbol in the input
     MissingDoubleDash,
@@ -66,6 +67,61 @@ pub enum ParseError {
         /// The symbol number of the first occurrence
         id: usize,
     },
+    /// Returned when an output of a [`LinearDiagram`](crate::LinearDiagram) is not a sum of
+    /// (optionally scaled) input symbols
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use autoperm::{parse_linear, ParseError};
+    ///
+    /// let diagram = parse_linear("a b -- a*b");
+    ///
+    /// assert_eq!(diagram, Err(ParseError::InvalidTerm{ term: "a*b".to_string(), id: 0 }));
+    /// ```
+    InvalidTerm {
+        /// The output that could not be parsed
+        term: String,
+        /// The symbol number of the output
+        id: usize,
+    },
+    /// Returned by [`autoperm_linear`](crate::autoperm_linear) when an output is not a single
+    /// input and the model doesn't [support weights](crate::Model::supports_weights)
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use autoperm::{autoperm_linear, ParseError};
+    /// use autoperm::models::Rust;
+    ///
+    /// let program = autoperm_linear("a b -- a+b", Rust::new());
+    ///
+    /// assert_eq!(program, Err(ParseError::UnsupportedTerm{ term: "a+b".to_string(), id: 0 }));
+    /// ```
+    UnsupportedTerm {
+        /// The output the model can't generate
+        term: String,
+        /// The symbol number of the output
+        id: usize,
+    },
+    /// Returned when the two sides of a [`MultiStackDiagram`](crate::MultiStackDiagram) have a
+    /// different number of stacks
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use autoperm::{parse_stacks, ParseError};
+    ///
+    /// let diagram = parse_stacks("a | r -- a r");
+    ///
+    /// assert_eq!(diagram, Err(ParseError::StackCountMismatch{ inputs: 2, outputs: 1 }));
+    /// ```
+    StackCountMismatch {
+        /// The number of stacks before the "--"
+        inputs: usize,
+        /// The number of stacks after the "--"
+        outputs: usize,
+    },
 }
 
 impl std::fmt::Display for ParseError {
@@ -85,12 +141,98 @@ impl std::fmt::Display for ParseError {
             ParseError::SymbolNotDefined { symbol, id } => {
                 write!(f, "Symbol {} not defined at {}", symbol, id)
             }
+            ParseError::InvalidTerm { term, id } => {
+                write!(f, "Invalid term {} at {}", term, id)
+            }
+            ParseError::UnsupportedTerm { term, id } => {
+                write!(
+                    f,
+                    "Term {} at {} needs a model that supports weights",
+                    term, id
+                )
+            }
+            ParseError::StackCountMismatch { inputs, outputs } => {
+                write!(f, "{} stacks before -- but {} after", inputs, outputs)
+            }
+        }
+    }
+}
+
+impl ParseError {
+    /// Finds the part of the parsed stack effect that caused the error, as a range of bytes.
+    ///
+    /// Returns `None` when the error is about the stack effect as a whole. Symbol numbers count
+    /// the words on one side of the "--", skipping the `|` between stacks.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use autoperm::parse;
+    ///
+    /// let stack_effect = "a b -- b c";
+    /// let error = parse(stack_effect).unwrap_err();
+    ///
+    /// assert_eq!(error.span(stack_effect), Some(9..10));
+    /// ```
+    pub fn span(&self, stack_effect: &str) -> Option<std::ops::Range<usize>> {
+        let dash = stack_effect.find("--")?;
+        let (pops, pushes) = (0..dash, dash + 2..stack_effect.len());
+
+        // The byte range of a symbol on one side of the "--"
+        let symbol = |side: std::ops::Range<usize>, id: usize| {
+            let text = &stack_effect[side.clone()];
+            let word = text
+                .split(|c: char| c.is_whitespace() || c == '|')
+                .filter(|word| !word.is_empty())
+                .nth(id)?;
+            let start = side.start + (word.as_ptr() as usize - text.as_ptr() as usize);
+            Some(start..start + word.len())
+        };
+
+        match self {
+            ParseError::MissingDoubleDash | ParseError::StackCountMismatch { .. } => None,
+            ParseError::AdditionalDoubleDash => {
+                let second = pushes.start + stack_effect[pushes].find("--")?;
+                Some(second..second + 2)
+            }
+            ParseError::SymbolDefinedTwice { second, .. } => symbol(pops, *second),
+            ParseError::SymbolNotDefined { id, .. }
+            | ParseError::InvalidTerm { id, .. }
+            | ParseError::UnsupportedTerm { id, .. } => symbol(pushes, *id),
         }
     }
 }
 
 /// A very simple parser for parsing [`StackEffectDiagram`](crate::StackEffectDiagram)s.
 pub fn parse(stack_effect: &str) -> Result<StackEffectDiagram, ParseError> {
+    let (pops, pushes) = split(stack_effect)?;
+
+    // map symbols to their input postitions
+    let symbols_to_positions = input_symbols(pops)?;
+
+    let input_size = symbols_to_positions.len();
+
+    // map output symbols to their starting positions
+    let mut mapping = Vec::with_capacity(pushes.len());
+    for symbol in pushes.split_whitespace() {
+        if let Some(pos) = symbols_to_positions.get(symbol) {
+            mapping.push(*pos);
+        } else {
+            return Err(ParseError::SymbolNotDefined {
+                symbol: symbol.to_string(),
+                id: mapping.len(),
+            });
+        }
+    }
+
+    Ok(StackEffectDiagram {
+        mapping,
+        inputs: input_size,
+    })
+}
+
+/// Splits a stack effect into the text before and after the "--"
+pub(crate) fn split(stack_effect: &str) -> Result<(&str, &str), ParseError> {
     let mut iter = stack_effect.split("--");
 
     let pops = match iter.next() {
@@ -107,7 +249,11 @@ pub fn parse(stack_effect: &str) -> Result<StackEffectDiagram, ParseError> {
         return Err(ParseError::AdditionalDoubleDash);
     }
 
-    // map symbols to their input postitions
+    Ok((pops, pushes))
+}
+
+/// Maps each input symbol to its position on the stack
+pub(crate) fn input_symbols(pops: &str) -> Result<HashMap<&str, usize>, ParseError> {
     let mut symbols_to_positions = HashMap::new();
     for (i, symbol) in pops.split_whitespace().enumerate() {
         // each symbol must only appear once in the input
@@ -122,23 +268,5 @@ pub fn parse(stack_effect: &str) -> Result<StackEffectDiagram, ParseError> {
         }
     }
 
-    let input_size = symbols_to_positions.len();
-
-    // map output symbols to their starting positions
-    let mut mapping = Vec::with_capacity(pushes.len());
-    for symbol in pushes.split_whitespace() {
-        if let Some(pos) = symbols_to_positions.get(symbol) {
-            mapping.push(*pos);
-        } else {
-            return Err(ParseError::SymbolNotDefined {
-                symbol: symbol.to_string(),
-                id: mapping.len(),
-            });
-        }
-    }
-
-    Ok(StackEffectDiagram {
-        mapping,
-        inputs: input_size,
-    })
+    Ok(symbols_to_positions)
 }
@@ -0,0 +1,279 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fmt,
+};
+
+use crate::StackEffectDiagram;
+
+/// The most paths [`search`] follows before giving up
+const MAX_STATES: usize = 500_000;
+
+/// A named stack shuffle that a target can execute, such as `swap` or `dup_x1` on the JVM
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Primitive {
+    /// The name emitted for the primitive
+    pub name: String,
+    /// What the primitive does to the stack
+    pub diagram: StackEffectDiagram,
+    /// How expensive the primitive is, [`search`] minimizes the total cost
+    pub cost: usize,
+}
+
+impl Primitive {
+    /// Creates a primitive
+    pub fn new(name: impl Into<String>, diagram: StackEffectDiagram, cost: usize) -> Self {
+        Self {
+            name: name.into(),
+            diagram,
+            cost,
+        }
+    }
+}
+
+/// Returned when [`search`] can't find a sequence of primitives
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum SearchError {
+    /// No sequence of at most `depth` primitives applies the target diagram
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use autoperm::{parse, search, Primitive, SearchError};
+    ///
+    /// // Nothing can be duplicated with only SWAP
+    /// let swap = Primitive::new("SWAP", parse("a b -- b a").unwrap(), 1);
+    ///
+    /// assert_eq!(
+    ///     search(&[swap], &parse("a -- a a").unwrap(), 5),
+    ///     Err(SearchError::NotExpressible { depth: 5 })
+    /// );
+    /// ```
+    NotExpressible {
+        /// The depth limit of the search
+        depth: usize,
+    },
+    /// The search looked at too many different stacks without finding the target
+    TooLarge,
+}
+
+impl std::error::Error for SearchError {}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::NotExpressible { depth } => write!(
+                f,
+                "The diagram can't be expressed with at most {} primitives",
+                depth
+            ),
+            SearchError::TooLarge => write!(f, "The diagram is too large to search"),
+        }
+    }
+}
+
+/// Finds the cheapest sequence of primitives that applies a target [`StackEffectDiagram`](crate::StackEffectDiagram).
+///
+/// At most `depth` primitives are used, and the primitives never reach below the inputs of the
+/// target. Returns the names of the primitives in the order they run.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::{parse, search, Primitive};
+///
+/// // Some of the JVM's stack instructions
+/// let primitives = [
+///     Primitive::new("swap", parse("a b -- b a").unwrap(), 1),
+///     Primitive::new("dup", parse("a -- a a").unwrap(), 1),
+///     Primitive::new("dup_x1", parse("a b -- b a b").unwrap(), 1),
+///     Primitive::new("pop", parse("a --").unwrap(), 1),
+/// ];
+///
+/// let target = parse("a b -- b b a").unwrap();
+///
+/// assert_eq!(search(&primitives, &target, 4), Ok(vec!["dup_x1", "swap"]));
+/// ```
+pub fn search<'a>(
+    primitives: &'a [Primitive],
+    target: &StackEffectDiagram,
+    depth: usize,
+) -> Result<Vec<&'a str>, SearchError> {
+    Ok(cheapest(primitives, target, depth, usize::MAX)?
+        .into_iter()
+        .map(|i| primitives[i].name.as_str())
+        .collect())
+}
+
+/// A path the search followed, with the node and primitive it continues. The stack it reached
+/// is only kept in the queue to save memory.
+struct Node {
+    cost: usize,
+    used: usize,
+    from: Option<(usize, usize)>,
+}
+
+/// Lower bounds on how many primitives and how much cost it takes to turn a stack into the
+/// target.
+///
+/// Primitives only copy and drop the items they are given, so each one can add or remove a fixed
+/// number of copies of values. Counting the copies the stack is missing and the copies it has too
+/// many of gives bounds that never overestimate, which keeps the search exact.
+struct Estimate<'a> {
+    target: &'a [usize],
+    /// How many copies of each input the target holds
+    needed: Vec<usize>,
+    /// The most copies a primitive adds and the cheapest (cost, copies) rate of adding them
+    adds: Option<(usize, (usize, usize))>,
+    /// The same for removing copies
+    removes: Option<(usize, (usize, usize))>,
+    cheapest: usize,
+}
+
+impl<'a> Estimate<'a> {
+    fn new(primitives: &[Primitive], target: &'a StackEffectDiagram) -> Self {
+        let mut needed = vec![0; target.inputs];
+        target.mapping.iter().for_each(|&i| needed[i] += 1);
+
+        let rate = |copies: &dyn Fn(&Primitive) -> usize| {
+            let useful = primitives.iter().filter(|p| copies(p) > 0);
+            let most = useful.clone().map(copies).max()?;
+            let best = useful
+                .map(|p| (p.cost, copies(p)))
+                .min_by(|(c1, n1), (c2, n2)| (c1 * n2).cmp(&(c2 * n1)))?;
+            Some((most, best))
+        };
+        let distinct = |p: &Primitive| {
+            let mut used = p.diagram.mapping.clone();
+            used.sort_unstable();
+            used.dedup();
+            used.len()
+        };
+
+        Estimate {
+            target: &target.mapping,
+            needed,
+            adds: rate(&|p| p.diagram.mapping.len() - distinct(p)),
+            removes: rate(&|p| p.diagram.inputs - distinct(p)),
+            cheapest: primitives.iter().map(|p| p.cost).min().unwrap_or(0),
+        }
+    }
+
+    /// Returns the fewest primitives and the least cost that can reach the target, or `None`
+    /// when it can't be reached
+    fn estimate(&self, stack: &[usize]) -> Option<(usize, usize)> {
+        if stack == self.target {
+            return Some((0, 0));
+        }
+
+        let mut have = vec![0; self.needed.len()];
+        stack.iter().for_each(|&i| have[i] += 1);
+
+        let (mut missing, mut extra) = (0, 0);
+        for (&have, &needed) in have.iter().zip(&self.needed) {
+            // Values are only ever copied or dropped, a value that is gone can't come back
+            if have == 0 && needed > 0 {
+                return None;
+            }
+            missing += needed.saturating_sub(have);
+            extra += have.saturating_sub(needed);
+        }
+
+        let (mut steps, mut cost) = (1, self.cheapest);
+        for (copies, rate) in [(missing, self.adds), (extra, self.removes)] {
+            if copies > 0 {
+                let (most, (rate_cost, rate_copies)) = rate?;
+                steps = std::cmp::max(steps, copies.div_ceil(most));
+                cost = std::cmp::max(cost, copies.saturating_mul(rate_cost).div_ceil(rate_copies));
+            }
+        }
+
+        Some((steps, cost))
+    }
+}
+
+/// Finds the indices of the cheapest sequence of at most `depth` primitives that costs at most
+/// `max_cost`, with an A* search guided by [`Estimate`].
+///
+/// Every stack keeps the cost and length of the best path found to it. A new path is only
+/// followed when it is cheaper or shorter than that one, as a path that is neither can't lead
+/// anywhere the kept path doesn't.
+pub(crate) fn cheapest(
+    primitives: &[Primitive],
+    target: &StackEffectDiagram,
+    depth: usize,
+    max_cost: usize,
+) -> Result<Vec<usize>, SearchError> {
+    let estimate = Estimate::new(primitives, target);
+    let not_expressible = SearchError::NotExpressible { depth };
+
+    let start: Vec<usize> = (0..target.inputs).collect();
+    let (steps, bound) = estimate.estimate(&start).ok_or(not_expressible)?;
+    if steps > depth || bound > max_cost {
+        return Err(not_expressible);
+    }
+
+    let mut best: HashMap<Vec<usize>, (usize, usize)> = HashMap::from([(start.clone(), (0, 0))]);
+    let mut nodes = vec![Node {
+        cost: 0,
+        used: 0,
+        from: None,
+    }];
+    // Ties go to the node closest to the target
+    let mut queue = BinaryHeap::from([Reverse((bound, bound, 0, start))]);
+
+    while let Some(Reverse((_, _, id, stack))) = queue.pop() {
+        let Node { cost, used, .. } = nodes[id];
+
+        let kept = best[&stack];
+        if kept != (cost, used) && kept.0 <= cost && kept.1 <= used {
+            continue;
+        }
+
+        if stack == target.mapping {
+            let mut sequence = Vec::new();
+            let mut id = id;
+            while let Some((previous, primitive)) = nodes[id].from {
+                sequence.push(primitive);
+                id = previous;
+            }
+            sequence.reverse();
+            return Ok(sequence);
+        }
+
+        for (i, primitive) in primitives.iter().enumerate() {
+            let Some(split) = stack.len().checked_sub(primitive.diagram.inputs) else {
+                continue;
+            };
+            let mut next = stack[..split].to_vec();
+            next.extend(primitive.diagram.mapping.iter().map(|&j| stack[split + j]));
+
+            let (cost, used) = (cost + primitive.cost, used + 1);
+            let Some((steps, bound)) = estimate.estimate(&next) else {
+                continue;
+            };
+            if used + steps > depth || cost + bound > max_cost {
+                continue;
+            }
+            if best
+                .get(&next)
+                .is_some_and(|&(c, u)| c <= cost && u <= used)
+            {
+                continue;
+            }
+            if nodes.len() >= MAX_STATES {
+                return Err(SearchError::TooLarge);
+            }
+
+            best.insert(next.clone(), (cost, used));
+            nodes.push(Node {
+                cost,
+                used,
+                from: Some((id, i)),
+            });
+            queue.push(Reverse((cost + bound, bound, nodes.len() - 1, next)));
+        }
+    }
+
+    Err(not_expressible)
+}
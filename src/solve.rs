@@ -1,8 +1,12 @@
-use crate::parse::StackEffectDiagram;
-use petgraph::prelude::*;
+use crate::{
+    graph::{Component, MoveGraph, Walk},
+    options::SolveOptions,
+    parse::StackEffectDiagram,
+};
 
 /// Represents an instruction within the computation model
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[non_exhaustive]
 pub enum Instruction {
     /// Clear / Zero the cell at a given index
     Clear {
@@ -18,6 +22,15 @@ pub enum Instruction {
         /// Note: This list will never contain `cell`
         to: Vec<isize>,
     },
+    /// Delete the data in a cell and add multiples of it to a list of other cells
+    WeightedMov {
+        /// The index of the cell to Mov
+        cell: isize,
+        /// The list of cells to add the data to, each with the number of times it is added.
+        ///
+        /// Note: This list will never contain `cell`
+        to: Vec<(isize, isize)>,
+    },
     /// Start the program with the assumption that the top of the stack is at a given index
     Start {
         /// The index of the top of the stack
@@ -55,94 +68,290 @@ pub enum Instruction {
 /// ]);
 /// ```
 pub fn solve(diagram: &StackEffectDiagram) -> Vec<Instruction> {
-    let mapping = &diagram.mapping;
-    let inputs = diagram.inputs;
+    solve_with(diagram, &SolveOptions::new())
+}
 
-    // The index of the temporary variable. Place it above the highest item
-    let temp = std::cmp::max(inputs, mapping.len()) as isize;
+/// Given a [`StackEffectDiagram`](crate::parse::StackEffectDiagram) generate a list of instructions
+/// to apply that diagram, using what the [`SolveOptions`](crate::SolveOptions) say is known about
+/// the tape to decide which cells need to be cleared.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::{solve_with, Instruction, SolveOptions, StackEffectDiagram};
+///
+/// // a b -- b a
+/// let diagram = StackEffectDiagram {
+///     inputs: 2,
+///     mapping: vec![1, 0],
+/// };
+///
+/// // The temporary cell may hold garbage
+/// let options = SolveOptions::new().zero_above(false);
+///
+/// assert_eq!(solve_with(&diagram, &options), vec![
+///     Instruction::Start { cell: 1},
+///     Instruction::Clear { cell: 2 },
+///     Instruction::Mov { cell: 1, to: vec![2] },
+///     Instruction::Mov { cell: 0, to: vec![1] },
+///     Instruction::Mov { cell: 2, to: vec![0] },
+///     Instruction::Top { cell: 1 },
+/// ]);
+/// ```
+pub fn solve_with(diagram: &StackEffectDiagram, options: &SolveOptions) -> Vec<Instruction> {
+    solve_iter_with(diagram, options).collect()
+}
 
-    let edges: Vec<_> = mapping.iter().enumerate().map(|(i, j)| (*j, i)).collect();
+/// Lazily generate the instructions to apply a [`StackEffectDiagram`](crate::parse::StackEffectDiagram).
+///
+/// The instructions are the same as [`solve`](crate::solve) returns, but they are produced one
+/// at a time so a program for a huge diagram can be generated without holding every
+/// instruction in memory.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::{generate, parse, solve_iter};
+/// use autoperm::models::Brainfuck;
+///
+/// let diagram = parse("a b -- b a").unwrap();
+///
+/// let program = generate(solve_iter(&diagram), Brainfuck::new());
+///
+/// assert_eq!(program, "[->+<]<[->+<]>>[-<<+>>]<");
+/// ```
+pub fn solve_iter(diagram: &StackEffectDiagram) -> impl Iterator<Item = Instruction> + '_ {
+    Instructions::stack(diagram, SolveOptions::new())
+}
 
-    let mut digraph: DiGraph<(), (), usize> = DiGraph::from_edges(edges);
+/// Lazily generate the instructions to apply a [`StackEffectDiagram`](crate::parse::StackEffectDiagram)
+/// with the given [`SolveOptions`](crate::SolveOptions).
+///
+/// See [`solve_iter`](crate::solve_iter) and [`solve_with`](crate::solve_with).
+pub fn solve_iter_with<'a>(
+    diagram: &'a StackEffectDiagram,
+    options: &SolveOptions,
+) -> impl Iterator<Item = Instruction> + 'a {
+    Instructions::stack(diagram, options.clone())
+}
 
-    // if the number of outputs is less than the number of inputs those nodes need to be cleared
-    if mapping.len() < inputs {
-        (mapping.len()..inputs).for_each(|_| {
-            digraph.add_node(());
-        })
-    }
+/// Where [`Instructions`] is in the program
+enum State {
+    Start,
+    ClearTemp,
+    Components,
+    /// Inside a cycle, about to move `node`. The cycle started by parking `first`
+    Cycle {
+        node: usize,
+        root: usize,
+        first: usize,
+    },
+    /// The second half of a self loop, or the end of a cycle
+    FromTemp(usize),
+    Top,
+    Done,
+}
 
-    // Reversing the ouput of tarjan's strongly connected components creates the program
-    let tarjan = petgraph::algo::tarjan_scc(&digraph);
+/// How the nodes of a [`MoveGraph`] relate to cells on the tape
+pub(crate) enum Cells<'a> {
+    /// Node `i` is cell `i` of the stack a diagram is applied to
+    Stack {
+        diagram: &'a StackEffectDiagram,
+        options: SolveOptions,
+    },
+    /// Node `i` is `cells[i]`. Nodes marked as `sources` are cleared when they have no targets,
+    /// the others are only destinations and start empty.
+    Sparse {
+        cells: Vec<isize>,
+        sources: Vec<bool>,
+    },
+}
 
-    let mut instructions = vec![Instruction::Start {
-        cell: inputs as isize - 1,
-    }];
+impl Cells<'_> {
+    fn cell(&self, node: usize) -> isize {
+        match self {
+            Cells::Stack { options, .. } => options.cell(node as isize),
+            Cells::Sparse { cells, .. } => cells[node],
+        }
+    }
 
-    for component in tarjan {
-        if component.len() == 1 {
-            // get the neighbors as a usize
-            let neighbors = get_neighbors(&digraph, component[0]);
-            let index = component[0].index() as isize;
+    /// Whether a node without any targets has to be cleared
+    fn clears(&self, node: usize) -> bool {
+        match self {
+            Cells::Stack { diagram, options } => {
+                // Dropped cells that are overwritten must be cleared because `Mov` adds to its
+                // destinations, the others only when they have to be left empty
+                let overwritten = node < diagram.mapping.len();
+                let dropped = node < diagram.inputs;
 
-            if neighbors.is_empty() {
-                if index < inputs as isize {
-                    instructions.push(Instruction::Clear { cell: index });
-                }
-            } else if neighbors.contains(&index) {
-                if neighbors.len() > 1 {
-                    instructions.push(Instruction::Mov {
-                        cell: index,
-                        to: vec![temp],
-                    });
-                    instructions.push(Instruction::Mov {
-                        cell: temp,
-                        to: neighbors,
-                    });
-                }
-            } else {
-                instructions.push(Instruction::Mov {
-                    cell: index,
-                    to: neighbors,
-                });
+                (overwritten || (dropped && options.clears_dropped()))
+                    && !options.is_zero(node as isize, diagram.inputs)
             }
-        } else {
-            let mut iter = component.into_iter();
-
-            let last_index = iter.next().unwrap();
-            let last_neighbors = get_neighbors(&digraph, last_index);
-
-            instructions.push(Instruction::Mov {
-                cell: last_index.index() as isize,
-                to: vec![temp],
-            });
-
-            for node in iter {
-                let neighbors = get_neighbors(&digraph, node);
-                instructions.push(Instruction::Mov {
-                    cell: node.index() as isize,
-                    to: neighbors,
-                });
+            Cells::Sparse { sources, .. } => sources[node],
+        }
+    }
+
+    /// Whether the temporary cell has to be cleared before it is used. For [`Cells::Stack`] it
+    /// takes the position after the last node.
+    fn clears_temp(&self, nodes: usize) -> bool {
+        match self {
+            Cells::Stack { diagram, options } => {
+                !options.temp_is_zero(nodes as isize, diagram.inputs)
             }
+            Cells::Sparse { .. } => false,
+        }
+    }
+}
+
+/// Iterator over the instructions of a solution, see [`solve_iter`]
+pub(crate) struct Instructions<'a> {
+    cells: Cells<'a>,
+    graph: MoveGraph,
+    walk: Walk,
+    temp: isize,
+    state: State,
+}
+
+impl<'a> Instructions<'a> {
+    fn stack(diagram: &'a StackEffectDiagram, options: SolveOptions) -> Self {
+        let graph = MoveGraph::from_diagram(diagram);
+
+        // The temporary variable is placed above the highest item
+        let temp = options.temp(graph.len() as isize);
+        Self::new(graph, Cells::Stack { diagram, options }, temp)
+    }
+
+    /// Creates the instructions that apply the moves in a graph. `Start` and `Top` are only
+    /// included for [`Cells::Stack`].
+    pub(crate) fn new(graph: MoveGraph, cells: Cells<'a>, temp: isize) -> Self {
+        let walk = Walk::new(&graph);
+        let state = match cells {
+            Cells::Stack { .. } => State::Start,
+            Cells::Sparse { .. } => State::ClearTemp,
+        };
+
+        Instructions {
+            cells,
+            graph,
+            walk,
+            temp,
+            state,
+        }
+    }
 
-            instructions.push(Instruction::Mov {
-                cell: temp,
-                to: last_neighbors,
-            });
+    fn mov(&self, cell: isize, node: usize) -> Instruction {
+        Instruction::Mov {
+            cell,
+            to: self
+                .graph
+                .targets(node)
+                .iter()
+                .map(|&i| self.cells.cell(i))
+                .collect(),
         }
     }
 
-    instructions.push(Instruction::Top {
-        cell: mapping.len() as isize - 1,
-    });
+    fn park(&self, node: usize) -> Instruction {
+        Instruction::Mov {
+            cell: self.cells.cell(node),
+            to: vec![self.temp],
+        }
+    }
+
+    /// Starts the next component, returning its first instruction
+    fn component(&mut self) -> Option<Instruction> {
+        // Walking the components in reverse topological order creates the program
+        while let Some(component) = self.walk.next(&self.graph) {
+            match component {
+                Component::Node(node) => {
+                    let targets = self.graph.targets(node);
 
-    instructions
+                    if targets.is_empty() {
+                        if self.cells.clears(node) {
+                            return Some(Instruction::Clear {
+                                cell: self.cells.cell(node),
+                            });
+                        }
+                    } else if targets.contains(&node) {
+                        if targets.len() > 1 {
+                            self.state = State::FromTemp(node);
+                            return Some(self.park(node));
+                        }
+                    } else {
+                        return Some(self.mov(self.cells.cell(node), node));
+                    }
+                }
+                Component::Cycle(root) => {
+                    let first = self.graph.source(root).unwrap();
+                    self.state = State::Cycle {
+                        node: self.graph.source(first).unwrap(),
+                        root,
+                        first,
+                    };
+                    return Some(self.park(first));
+                }
+            }
+        }
+
+        self.state = match self.cells {
+            Cells::Stack { .. } => State::Top,
+            Cells::Sparse { .. } => State::Done,
+        };
+        self.next()
+    }
 }
 
-fn get_neighbors<N, E, Ty, Ix>(graph: &Graph<N, E, Ty, Ix>, index: NodeIndex<Ix>) -> Vec<isize>
-where
-    Ty: petgraph::EdgeType,
-    Ix: petgraph::adj::IndexType,
-{
-    graph.neighbors(index).map(|i| i.index() as isize).collect()
+impl Iterator for Instructions<'_> {
+    type Item = Instruction;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.state {
+            State::Start => {
+                self.state = State::ClearTemp;
+                match &self.cells {
+                    Cells::Stack { diagram, options } => Some(Instruction::Start {
+                        cell: options.cell(diagram.inputs as isize - 1),
+                    }),
+                    Cells::Sparse { .. } => self.next(),
+                }
+            }
+            State::ClearTemp => {
+                self.state = State::Components;
+
+                // The temporary cell is emptied after every use, so it only has to be cleared once
+                if self.graph.uses_temp() && self.cells.clears_temp(self.graph.len()) {
+                    Some(Instruction::Clear { cell: self.temp })
+                } else {
+                    self.next()
+                }
+            }
+            State::Components => self.component(),
+            State::Cycle { node, root, first } => {
+                self.state = if node == root {
+                    State::FromTemp(first)
+                } else {
+                    State::Cycle {
+                        node: self.graph.source(node).unwrap(),
+                        root,
+                        first,
+                    }
+                };
+                Some(self.mov(self.cells.cell(node), node))
+            }
+            State::FromTemp(node) => {
+                self.state = State::Components;
+                Some(self.mov(self.temp, node))
+            }
+            State::Top => {
+                self.state = State::Done;
+                match &self.cells {
+                    Cells::Stack { diagram, options } => Some(Instruction::Top {
+                        cell: options.cell(diagram.mapping.len() as isize - 1),
+                    }),
+                    Cells::Sparse { .. } => None,
+                }
+            }
+            State::Done => None,
+        }
+    }
 }
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use crate::{
+    parse::{input_symbols, split},
+    solve_mapping, Instruction, MappingError, ParseError,
+};
+
+/// Represents a stack effect diagram over several stacks, such as a data stack and a return
+/// stack. Stacks are separated by `|` on both sides of the `--`.
+///
+/// # Example
+///
+/// ```
+/// use autoperm::{parse_stacks, MultiStackDiagram};
+///
+/// // >R
+/// let diagram = parse_stacks("a b | r -- a | r b").unwrap();
+///
+/// assert_eq!(diagram, MultiStackDiagram {
+///     inputs: vec![2, 1],
+///     mappings: vec![vec![(0, 0)], vec![(1, 0), (0, 1)]],
+/// });
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct MultiStackDiagram {
+    /// For each stack, the mapping of its output symbols to the stack and position they start at
+    pub mappings: Vec<Vec<(usize, usize)>>,
+    /// The number of input symbols on each stack
+    pub inputs: Vec<usize>,
+}
+
+/// Parses a [`MultiStackDiagram`](crate::MultiStackDiagram).
+///
+/// Symbols must be unique across every stack. The symbol numbers in errors count across the
+/// stacks from left to right.
+pub fn parse_stacks(stack_effect: &str) -> Result<MultiStackDiagram, ParseError> {
+    let (pops, pushes) = split(stack_effect)?;
+
+    let pops: Vec<_> = pops.split('|').collect();
+    let pushes: Vec<_> = pushes.split('|').collect();
+
+    if pops.len() != pushes.len() {
+        return Err(ParseError::StackCountMismatch {
+            inputs: pops.len(),
+            outputs: pushes.len(),
+        });
+    }
+
+    // map symbols to their stack and position, checking for duplicates across every stack
+    let mut symbols_to_positions: HashMap<&str, (usize, usize)> = HashMap::new();
+    let mut inputs = Vec::with_capacity(pops.len());
+    let mut count = 0;
+    for (stack, pops) in pops.into_iter().enumerate() {
+        let symbols = input_symbols(pops).map_err(|err| offset(err, count))?;
+
+        for (pos, symbol) in pops.split_whitespace().enumerate() {
+            if let Some(&(other, first)) = symbols_to_positions.get(symbol) {
+                let first = first + pops_before(&inputs, other);
+                return Err(ParseError::SymbolDefinedTwice {
+                    symbol: symbol.to_string(),
+                    first,
+                    second: count + pos,
+                });
+            }
+        }
+
+        count += symbols.len();
+        inputs.push(symbols.len());
+        symbols_to_positions.extend(symbols.into_iter().map(|(s, pos)| (s, (stack, pos))));
+    }
+
+    // map output symbols to their starting positions
+    let mut mappings = Vec::with_capacity(pushes.len());
+    let mut id = 0;
+    for pushes in pushes {
+        let mut mapping = Vec::new();
+        for symbol in pushes.split_whitespace() {
+            match symbols_to_positions.get(symbol) {
+                Some(pos) => mapping.push(*pos),
+                None => {
+                    return Err(ParseError::SymbolNotDefined {
+                        symbol: symbol.to_string(),
+                        id,
+                    })
+                }
+            }
+            id += 1;
+        }
+        mappings.push(mapping);
+    }
+
+    Ok(MultiStackDiagram { mappings, inputs })
+}
+
+/// The number of symbols on the stacks before `stack`
+fn pops_before(inputs: &[usize], stack: usize) -> usize {
+    inputs[..stack].iter().sum()
+}
+
+/// Shifts the symbol numbers of an error from a single stack by the symbols before it
+fn offset(err: ParseError, count: usize) -> ParseError {
+    match err {
+        ParseError::SymbolDefinedTwice {
+            symbol,
+            first,
+            second,
+        } => ParseError::SymbolDefinedTwice {
+            symbol,
+            first: first + count,
+            second: second + count,
+        },
+        err => err,
+    }
+}
+
+/// Given a [`MultiStackDiagram`](crate::MultiStackDiagram) generate a list of instructions to
+/// apply that diagram.
+///
+/// `bases[s]` is the cell holding the first input of stack `s` (the deepest item the diagram
+/// touches) and each stack grows toward higher cells. `temp` must be an empty cell outside of
+/// every stack. The first stack is the one the pointer starts and ends on, so `Start` and `Top`
+/// refer to its cells.
+///
+/// # Panics
+///
+/// Panics if there isn't exactly one base for each stack.
+///
+/// # Examples
+///
+/// ```
+/// use autoperm::{parse_stacks, solve_stacks, Instruction};
+///
+/// // R> with the return stack 100 cells to the right of the data stack
+/// let diagram = parse_stacks("a | r -- a r |").unwrap();
+///
+/// assert_eq!(solve_stacks(&diagram, &[0, 100], 2), Ok(vec![
+///     Instruction::Start { cell: 0 },
+///     Instruction::Mov { cell: 100, to: vec![1] },
+///     Instruction::Top { cell: 1 },
+/// ]));
+/// ```
+pub fn solve_stacks(
+    diagram: &MultiStackDiagram,
+    bases: &[isize],
+    temp: isize,
+) -> Result<Vec<Instruction>, MappingError> {
+    assert_eq!(
+        bases.len(),
+        diagram.inputs.len(),
+        "there must be exactly one base for each stack"
+    );
+
+    // Every input is a source, so that the dropped ones are cleared
+    let mut mapping: HashMap<isize, Vec<isize>> = HashMap::new();
+    for (stack, inputs) in diagram.inputs.iter().enumerate() {
+        for i in 0..*inputs {
+            mapping.insert(bases[stack] + i as isize, vec![]);
+        }
+    }
+
+    for (stack, outputs) in diagram.mappings.iter().enumerate() {
+        for (i, (from, j)) in outputs.iter().enumerate() {
+            mapping
+                .get_mut(&(bases[*from] + *j as isize))
+                .unwrap()
+                .push(bases[stack] + i as isize);
+        }
+    }
+
+    let mut instructions = vec![Instruction::Start {
+        cell: bases[0] + diagram.inputs[0] as isize - 1,
+    }];
+    instructions.extend(solve_mapping(&mapping, temp)?);
+    instructions.push(Instruction::Top {
+        cell: bases[0] + diagram.mappings[0].len() as isize - 1,
+    });
+
+    Ok(instructions)
+}
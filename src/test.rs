@@ -1,7 +1,19 @@
+use std::collections::HashMap;
+
 use bfi::TestResults;
 use quickcheck::TestResult;
 
-use crate::{generate, models::Brainfuck, parse, solve, StackEffectDiagram};
+use crate::{
+    autoperm, autoperm_bf, autoperm_linear, compose, forth, generate,
+    models::{
+        Brainfuck, BrainfuckCost, BrainfuckOptions, ClearLoop, Execute, Listing, LlvmIr,
+        PointerPosition, Rust, Syntax, Trace, Wat, C, X86_64,
+    },
+    optimize, parse, parse_linear, parse_listing, parse_listing_with, parse_stacks, search, solve,
+    solve_iter, solve_linear, solve_mapping, solve_stacks, solve_with, to_dot, to_dot_ordered,
+    Cost, ForthWords, Instruction, Layout, LinearDiagram, ListingError, MappingError, ParseError,
+    Primitive, SearchError, SolutionCache, SolveOptions, StackEffectDiagram,
+};
 
 fn test_brainfuck(code: &str, inputs: Vec<Vec<u8>>, outputs: Vec<Vec<u8>>) -> bool {
     println!("Testing: {}", code);
@@ -16,7 +28,7 @@ fn test_brainfuck(code: &str, inputs: Vec<Vec<u8>>, outputs: Vec<Vec<u8>>) -> bo
         }
         TestResults::Results(results) => {
             let mut failure = false;
-            for (_i, result) in results.iter().enumerate() {
+            for result in results.iter() {
                 match result {
                     bfi::TestResult::Ok => {}
                     bfi::TestResult::RunTimeError(e) => {
@@ -40,7 +52,16 @@ fn test_brainfuck(code: &str, inputs: Vec<Vec<u8>>, outputs: Vec<Vec<u8>>) -> bo
 }
 
 fn test_stackeffect(effect: &StackEffectDiagram) -> bool {
-    println!("Testing: {:?}", effect);
+    test_stackeffect_with(effect, &SolveOptions::new(), false)
+}
+
+/// Tests a stack effect, optionally filling the cells above the stack with garbage first
+fn test_stackeffect_with(
+    effect: &StackEffectDiagram,
+    options: &SolveOptions,
+    garbage: bool,
+) -> bool {
+    println!("Testing: {:?} {:?}", effect, options);
     if !effect.mapping.is_empty() {
         assert!(
             *effect.mapping.iter().max().unwrap() < effect.inputs,
@@ -49,44 +70,107 @@ fn test_stackeffect(effect: &StackEffectDiagram) -> bool {
     }
 
     // Solve the stack effect diagram
-    let instructions = solve(effect);
+    let instructions = solve_with(effect, options);
     println!("Instructions: {:#?}", instructions);
     let function = generate(instructions, Brainfuck::default());
 
+    // Cover every new cell and the temporary cell with garbage
+    let above = if garbage {
+        std::cmp::max(effect.inputs, effect.mapping.len()) + 1 - effect.inputs
+    } else {
+        0
+    };
+
+    let cell = |position| options.cell(position);
+    test_function_in(&function, effect.inputs, above, cell, |input| {
+        effect.mapping.iter().map(|&i| input[i]).collect()
+    })
+}
+
+fn test_linear(effect: &LinearDiagram) -> bool {
+    println!("Testing: {:?}", effect);
+
+    let instructions = solve_linear(effect);
+    println!("Instructions: {:#?}", instructions);
+    let function = generate(instructions, Brainfuck::default());
+
+    test_function(&function, effect.inputs, 0, |input| {
+        effect
+            .mapping
+            .iter()
+            .map(|terms| {
+                terms.iter().fold(0u8, |sum, &(i, weight)| {
+                    sum.wrapping_add(input[i].wrapping_mul(weight as u8))
+                })
+            })
+            .collect()
+    })
+}
+
+/// Runs a generated function on random inputs, with `above` cells of garbage above the stack,
+/// and compares the resulting stack against `expected`
+fn test_function<F>(function: &str, inputs: usize, above: usize, expected: F) -> bool
+where
+    F: Fn(&[u8]) -> Vec<u8>,
+{
+    test_function_in(function, inputs, above, |position| position, expected)
+}
+
+/// Like [`test_function`] for a stack whose positions are stored in the cells given by `cell`
+fn test_function_in<C, F>(function: &str, inputs: usize, above: usize, cell: C, expected: F) -> bool
+where
+    C: Fn(isize) -> isize,
+    F: Fn(&[u8]) -> Vec<u8>,
+{
+    let outputs = expected(&vec![0; inputs]).len();
+
+    // Start the harness left of every cell the function may touch, including the temporary cell
+    let positions = std::cmp::max(inputs + above, outputs + 1) as isize;
+    let mut ptr = (0..positions).map(&cell).min().unwrap() - 1;
+    let shift_to = |ptr: &mut isize, cell: isize| {
+        let diff = cell - std::mem::replace(ptr, cell);
+        if diff < 0 {
+            "<".repeat(diff.unsigned_abs())
+        } else {
+            ">".repeat(diff.unsigned_abs())
+        }
+    };
+
     // Create a testing harness
-    let mut reads: String = ",>".repeat(effect.inputs);
-    reads.pop();
-    let mut writes: String = ".<".repeat(effect.mapping.len());
-    writes.pop();
+    let mut reads = String::new();
+    for p in 0..(inputs + above) as isize {
+        reads += &shift_to(&mut ptr, cell(p));
+        reads += ",";
+    }
+    reads += &shift_to(&mut ptr, cell(inputs as isize - 1));
 
-    let bf = format!(">{}\n{}{}", reads, function, writes);
+    let mut writes = String::new();
+    ptr = cell(outputs as isize - 1);
+    for p in (0..outputs as isize).rev() {
+        writes += &shift_to(&mut ptr, cell(p));
+        writes += ".";
+    }
+
+    let bf = format!("{}\n{}{}", reads, function, writes);
 
     // Generate some random inputs and outputs
-    let mut inputs = Vec::new();
-    let mut outputs = Vec::new();
+    let mut tests_inputs = Vec::new();
+    let mut tests_outputs = Vec::new();
 
     for _ in 0..10 {
-        let input = (0..effect.inputs)
+        let mut input = (0..inputs)
             .map(|_| rand::random::<u8>())
             .collect::<Vec<_>>();
 
-        let mut output = effect
-            .mapping
-            .iter()
-            .map(|&i| input[i])
-            .rev()
-            .collect::<Vec<_>>();
+        let output = expected(&input).into_iter().rev().collect::<Vec<_>>();
 
-        // Add additional 0s to the end of the output
-        if output.len() < effect.mapping.len() {
-            output.extend(vec![0; effect.mapping.len() - output.len()]);
-        }
+        input.extend((0..above).map(|_| rand::random::<u8>()));
 
-        inputs.push(input);
-        outputs.push(output);
+        tests_inputs.push(input);
+        tests_outputs.push(output);
     }
 
-    test_brainfuck(&bf, inputs, outputs)
+    test_brainfuck(&bf, tests_inputs, tests_outputs)
 }
 
 /// Checks that the functions used in https://github.com/Alextopher/serotonin stdlib work
@@ -313,6 +397,2078 @@ fn regressions() {
     }));
 }
 
+#[test]
+fn known_cells() {
+    // Garbage above the stack must be cleared before it is used
+    let dirty = SolveOptions::new().zero_above(false);
+    for effect in [
+        "a b -- b a",
+        "a -- a a a a",
+        "a b c -- c",
+        "a b c d e f -- c d d f e e b",
+    ] {
+        assert!(test_stackeffect_with(&parse(effect).unwrap(), &dirty, true));
+    }
+
+    // Dropped cells above the new top are left alone
+    let lazy = SolveOptions::new().clear_dropped(false);
+    assert_eq!(
+        solve_with(&parse("a b c -- c").unwrap(), &lazy),
+        vec![
+            Instruction::Start { cell: 2 },
+            Instruction::Clear { cell: 0 },
+            Instruction::Mov {
+                cell: 2,
+                to: vec![0]
+            },
+            Instruction::Top { cell: 0 },
+        ]
+    );
+    assert!(test_stackeffect_with(
+        &parse("a b c d -- d b").unwrap(),
+        &lazy,
+        false
+    ));
+
+    // Known zero cells are never cleared
+    let known = SolveOptions::new().assume_zero(0).assume_zero(1);
+    assert_eq!(
+        solve_with(&parse("a b --").unwrap(), &known),
+        vec![
+            Instruction::Start { cell: 1 },
+            Instruction::Top { cell: -1 }
+        ]
+    );
+
+    // The defaults are what `solve` has always done
+    let diagram = parse("a b c d e f -- c d d f e e b").unwrap();
+    assert_eq!(solve_with(&diagram, &SolveOptions::new()), solve(&diagram));
+}
+
+#[test]
+fn layouts() {
+    let down = SolveOptions::new().layout(Layout::downward());
+
+    // The mirror image of the upward program
+    let diagram = parse("a b c -- c").unwrap();
+    assert_eq!(
+        generate(solve_with(&diagram, &down), Brainfuck::new()),
+        ">>[-]<[-]<[->>+<<]>>"
+    );
+
+    // Known cells are cells of the tape
+    assert_eq!(
+        solve_with(&parse("a b --").unwrap(), &down.clone().assume_zero(-1)),
+        vec![
+            Instruction::Start { cell: -1 },
+            Instruction::Clear { cell: 0 },
+            Instruction::Top { cell: 1 },
+        ]
+    );
+
+    for effect in [
+        "a b -- b a",
+        "a -- a a a a",
+        "a b c d -- d c a b",
+        "a b c d e f -- c d d f e e b",
+    ] {
+        let diagram = parse(effect).unwrap();
+        assert!(test_stackeffect_with(&diagram, &down, false));
+        assert!(test_stackeffect_with(
+            &diagram,
+            &down.clone().zero_above(false),
+            true
+        ));
+    }
+}
+
+#[test]
+fn strided_layouts() {
+    // Value and flag pairs with the temporary value in the flag after the top
+    let gap = Layout::upward().stride(2).temp_gap(1);
+    let options = SolveOptions::new().layout(gap);
+    assert_eq!(
+        solve_with(&parse("a -- a a").unwrap(), &options),
+        vec![
+            Instruction::Start { cell: 0 },
+            Instruction::Clear { cell: 3 },
+            Instruction::Mov {
+                cell: 0,
+                to: vec![3]
+            },
+            Instruction::Mov {
+                cell: 3,
+                to: vec![2, 0]
+            },
+            Instruction::Top { cell: 2 },
+        ]
+    );
+
+    // A gap that isn't between two items is rejected when the layout is built
+    assert!(std::panic::catch_unwind(|| Layout::upward().temp_gap(1)).is_err());
+    assert!(std::panic::catch_unwind(|| gap.stride(1)).is_err());
+    assert!(std::panic::catch_unwind(|| Layout::downward().stride(3).temp_gap(3)).is_err());
+
+    for layout in [
+        gap,
+        Layout::upward().stride(3).base(4),
+        Layout::downward().stride(2).temp_gap(1),
+        Layout::downward().stride(4).base(-3).temp_gap(2),
+    ] {
+        for effect in [
+            "a b -- b a",
+            "a -- a a a a",
+            "a b c -- c",
+            "a b c d e f -- c d d f e e b",
+        ] {
+            let options = SolveOptions::new().layout(layout);
+            assert!(test_stackeffect_with(
+                &parse(effect).unwrap(),
+                &options,
+                false
+            ));
+        }
+    }
+}
+
+#[quickcheck]
+fn quickcheck_strided(i: u8, v: Vec<u8>, stride: u8, base: i8) -> TestResult {
+    if i == 0 || *v.iter().max().unwrap_or(&0) >= i || !(2..5).contains(&stride) {
+        TestResult::discard()
+    } else {
+        let layout = Layout::downward()
+            .stride(stride as usize)
+            .base(base as isize)
+            .temp_gap(1);
+        TestResult::from_bool(test_stackeffect_with(
+            &StackEffectDiagram {
+                inputs: i as usize,
+                mapping: v.into_iter().map(|i| i as usize).collect(),
+            },
+            &SolveOptions::new().layout(layout),
+            false,
+        ))
+    }
+}
+
+#[quickcheck]
+fn quickcheck_downward(i: u8, v: Vec<u8>) -> TestResult {
+    if i == 0 || *v.iter().max().unwrap_or(&0) >= i {
+        TestResult::discard()
+    } else {
+        TestResult::from_bool(test_stackeffect_with(
+            &StackEffectDiagram {
+                inputs: i as usize,
+                mapping: v.into_iter().map(|i| i as usize).collect(),
+            },
+            &SolveOptions::new().layout(Layout::downward()),
+            false,
+        ))
+    }
+}
+
+#[quickcheck]
+fn quickcheck_garbage(i: u8, v: Vec<u8>) -> TestResult {
+    if i == 0 || *v.iter().max().unwrap_or(&0) >= i {
+        TestResult::discard()
+    } else {
+        TestResult::from_bool(test_stackeffect_with(
+            &StackEffectDiagram {
+                inputs: i as usize,
+                mapping: v.into_iter().map(|i| i as usize).collect(),
+            },
+            &SolveOptions::new().zero_above(false),
+            true,
+        ))
+    }
+}
+
+#[test]
+fn linear() {
+    for effect in [
+        "a b -- a+b",
+        "a -- a a*3",
+        "a b -- a-b",
+        "a b -- b a+b",
+        "a b c -- c*2+a b-a a+b+c",
+        "a b -- a-a b",
+        "a b c -- b+c a+c a+b",
+        "a b -- -a -b*5 3*a*2",
+    ] {
+        assert!(test_linear(&parse_linear(effect).unwrap()), "{}", effect);
+    }
+
+    assert_eq!(
+        parse_linear("a b -- 2*a-b+a"),
+        Ok(LinearDiagram {
+            inputs: 2,
+            mapping: vec![vec![(0, 3), (1, -1)]],
+        })
+    );
+    assert_eq!(
+        parse_linear("a -- a+"),
+        Err(ParseError::InvalidTerm {
+            term: "a+".to_string(),
+            id: 0
+        })
+    );
+    assert_eq!(
+        parse_linear("a -- 3"),
+        Err(ParseError::InvalidTerm {
+            term: "3".to_string(),
+            id: 0
+        })
+    );
+    assert_eq!(
+        parse_linear("a -- a b+c"),
+        Err(ParseError::SymbolNotDefined {
+            symbol: "b".to_string(),
+            id: 1
+        })
+    );
+
+    // Models that only rearrange values can't add them together
+    assert_eq!(
+        autoperm_linear("a b -- b a+b", Rust::new()),
+        Err(ParseError::UnsupportedTerm {
+            term: "a+b".to_string(),
+            id: 1
+        })
+    );
+    let mut stack = vec![1, 2];
+    assert_eq!(
+        autoperm_linear("a b -- a*2", Execute::new(&mut stack)).map(|_| ()),
+        Err(ParseError::UnsupportedTerm {
+            term: "a*2".to_string(),
+            id: 0
+        })
+    );
+    assert_eq!(
+        autoperm_linear("a b -- b a", Execute::new(&mut stack)).map(|stack| stack.clone()),
+        Ok(vec![2, 1])
+    );
+    assert!(autoperm_linear("a b -- a+b", Brainfuck::new()).is_ok());
+}
+
+#[quickcheck]
+fn quickcheck_linear(i: u8, v: Vec<Vec<(u8, i8)>>) -> TestResult {
+    if i == 0 || v.iter().flatten().any(|(j, _)| *j >= i) {
+        return TestResult::discard();
+    }
+
+    // Merge repeated inputs the same way the parser would
+    let mapping = v
+        .into_iter()
+        .map(|terms| {
+            let mut merged = std::collections::BTreeMap::new();
+            for (j, weight) in terms {
+                *merged.entry(j as usize).or_insert(0) += weight as isize;
+            }
+            merged.into_iter().filter(|(_, w)| *w != 0).collect()
+        })
+        .collect();
+
+    TestResult::from_bool(test_linear(&LinearDiagram {
+        inputs: i as usize,
+        mapping,
+    }))
+}
+
+#[quickcheck]
+fn quickcheck_linear_plain(i: u8, v: Vec<u8>) -> TestResult {
+    if i == 0 || *v.iter().max().unwrap_or(&0) >= i {
+        TestResult::discard()
+    } else {
+        TestResult::from_bool(test_linear(&LinearDiagram::from(StackEffectDiagram {
+            inputs: i as usize,
+            mapping: v.into_iter().map(|i| i as usize).collect(),
+        })))
+    }
+}
+
+/// The petgraph based solver `solve` used before it walked the functional graph directly
+fn reference_solve(diagram: &StackEffectDiagram) -> Vec<Instruction> {
+    use petgraph::prelude::*;
+
+    fn get_neighbors(graph: &DiGraph<(), (), usize>, index: NodeIndex<usize>) -> Vec<isize> {
+        graph.neighbors(index).map(|i| i.index() as isize).collect()
+    }
+
+    let mapping = &diagram.mapping;
+    let inputs = diagram.inputs;
+    let temp = std::cmp::max(inputs, mapping.len()) as isize;
+
+    let edges: Vec<_> = mapping.iter().enumerate().map(|(i, j)| (*j, i)).collect();
+    let mut digraph: DiGraph<(), (), usize> = DiGraph::from_edges(edges);
+    if mapping.len() < inputs {
+        (mapping.len()..inputs).for_each(|_| {
+            digraph.add_node(());
+        })
+    }
+
+    let mut instructions = vec![Instruction::Start {
+        cell: inputs as isize - 1,
+    }];
+
+    for component in petgraph::algo::tarjan_scc(&digraph) {
+        if component.len() == 1 {
+            let neighbors = get_neighbors(&digraph, component[0]);
+            let index = component[0].index() as isize;
+
+            if neighbors.is_empty() {
+                if index < inputs as isize {
+                    instructions.push(Instruction::Clear { cell: index });
+                }
+            } else if neighbors.contains(&index) {
+                if neighbors.len() > 1 {
+                    instructions.push(Instruction::Mov {
+                        cell: index,
+                        to: vec![temp],
+                    });
+                    instructions.push(Instruction::Mov {
+                        cell: temp,
+                        to: neighbors,
+                    });
+                }
+            } else {
+                instructions.push(Instruction::Mov {
+                    cell: index,
+                    to: neighbors,
+                });
+            }
+        } else {
+            let mut iter = component.into_iter();
+            let last_index = iter.next().unwrap();
+            let last_neighbors = get_neighbors(&digraph, last_index);
+
+            instructions.push(Instruction::Mov {
+                cell: last_index.index() as isize,
+                to: vec![temp],
+            });
+            for node in iter {
+                instructions.push(Instruction::Mov {
+                    cell: node.index() as isize,
+                    to: get_neighbors(&digraph, node),
+                });
+            }
+            instructions.push(Instruction::Mov {
+                cell: temp,
+                to: last_neighbors,
+            });
+        }
+    }
+
+    instructions.push(Instruction::Top {
+        cell: mapping.len() as isize - 1,
+    });
+
+    instructions
+}
+
+/// `solve_linear` finds components with the same search petgraph's `tarjan_scc` uses
+#[quickcheck]
+fn quickcheck_strongly_connected(n: u8, edges: Vec<(u8, u8)>) -> TestResult {
+    use petgraph::prelude::*;
+
+    if n == 0 || edges.iter().any(|&(a, b)| a >= n || b >= n) {
+        return TestResult::discard();
+    }
+
+    let mut digraph: DiGraph<(), (), usize> = DiGraph::default();
+    (0..n).for_each(|_| {
+        digraph.add_node(());
+    });
+    for &(a, b) in &edges {
+        digraph.add_edge(NodeIndex::new(a as usize), NodeIndex::new(b as usize), ());
+    }
+    let expected: Vec<Vec<usize>> = petgraph::algo::tarjan_scc(&digraph)
+        .into_iter()
+        .map(|component| component.into_iter().map(|node| node.index()).collect())
+        .collect();
+
+    // petgraph lists the newest edge first
+    let mut targets = vec![Vec::new(); n as usize];
+    for &(a, b) in edges.iter().rev() {
+        targets[a as usize].push(b as usize);
+    }
+    let components = crate::graph::strongly_connected(n as usize, 0..n as usize, |node| {
+        targets[node].iter().copied()
+    });
+
+    TestResult::from_bool(components == expected)
+}
+
+#[quickcheck]
+fn quickcheck_reference(i: u8, v: Vec<u8>) -> TestResult {
+    if i == 0 || *v.iter().max().unwrap_or(&0) >= i {
+        TestResult::discard()
+    } else {
+        let diagram = StackEffectDiagram {
+            inputs: i as usize,
+            mapping: v.into_iter().map(|i| i as usize).collect(),
+        };
+        TestResult::from_bool(solve(&diagram) == reference_solve(&diagram))
+    }
+}
+
+#[test]
+fn large() {
+    // Rotate a large stack by a third of its size
+    let size = 30_000;
+    let diagram = StackEffectDiagram {
+        inputs: size,
+        mapping: (0..size).map(|i| (i + size / 3) % size).collect(),
+    };
+    assert_eq!(solve(&diagram), reference_solve(&diagram));
+
+    // A long chain of copies
+    let diagram = StackEffectDiagram {
+        inputs: size,
+        mapping: (0..2 * size).map(|i| i / 2).collect(),
+    };
+    assert_eq!(solve(&diagram), reference_solve(&diagram));
+
+    // Streaming the instructions gives the same program
+    assert_eq!(
+        generate(solve_iter(&diagram), Brainfuck::new()),
+        generate(solve(&diagram), Brainfuck::new())
+    );
+}
+
+/// Runs instructions directly on a sparse tape
+fn simulate(instructions: &[Instruction], tape: &mut HashMap<isize, u8>) {
+    for instruction in instructions {
+        match instruction {
+            Instruction::Clear { cell } => {
+                tape.insert(*cell, 0);
+            }
+            Instruction::Mov { cell, to } => {
+                let value = tape.insert(*cell, 0).unwrap_or(0);
+                for target in to {
+                    let entry = tape.entry(*target).or_insert(0);
+                    *entry = entry.wrapping_add(value);
+                }
+            }
+            Instruction::WeightedMov { cell, to } => {
+                let value = tape.insert(*cell, 0).unwrap_or(0);
+                for (target, weight) in to {
+                    let entry = tape.entry(*target).or_insert(0);
+                    *entry = entry.wrapping_add(value.wrapping_mul(*weight as u8));
+                }
+            }
+            Instruction::Start { .. } | Instruction::Top { .. } => {}
+        }
+    }
+}
+
+#[test]
+fn mapping() {
+    // Move the top of the stack into a variable and a variable onto the stack
+    let mapping = HashMap::from([(2, vec![-5, 3]), (-5, vec![2]), (1, vec![])]);
+    let instructions = solve_mapping(&mapping, 4).unwrap();
+
+    let mut tape = HashMap::from([(-5, 10), (0, 20), (1, 30), (2, 40)]);
+    simulate(&instructions, &mut tape);
+    assert_eq!(
+        tape,
+        HashMap::from([(-5, 40), (0, 20), (1, 0), (2, 10), (3, 40), (4, 0)])
+    );
+
+    assert_eq!(
+        solve_mapping(&HashMap::from([(0, vec![1])]), 1),
+        Err(MappingError::TempInUse { cell: 1 })
+    );
+    assert_eq!(
+        solve_mapping(&HashMap::from([(0, vec![2, 2])]), 3),
+        Err(MappingError::DestinationUsedTwice { cell: 2 })
+    );
+}
+
+#[quickcheck]
+fn quickcheck_mapping(sources: Vec<i8>, destinations: Vec<(i8, u8)>) -> TestResult {
+    let mut sources = sources;
+    sources.sort_unstable();
+    sources.dedup();
+    if sources.is_empty() || sources.contains(&i8::MAX) {
+        return TestResult::discard();
+    }
+
+    // Give each destination to one of the sources
+    let mut mapping: HashMap<isize, Vec<isize>> =
+        sources.iter().map(|&s| (s as isize, vec![])).collect();
+    let mut seen = std::collections::HashSet::new();
+    for (destination, source) in destinations {
+        if destination == i8::MAX || !seen.insert(destination) {
+            continue;
+        }
+        let source = sources[source as usize % sources.len()] as isize;
+        mapping.get_mut(&source).unwrap().push(destination as isize);
+    }
+
+    let instructions = solve_mapping(&mapping, i8::MAX as isize).unwrap();
+
+    let before: HashMap<isize, u8> = (i8::MIN..i8::MAX)
+        .map(|cell| (cell as isize, rand::random::<u8>()))
+        .filter(|(cell, _)| !seen.contains(&(*cell as i8)) || mapping.contains_key(cell))
+        .collect();
+    let mut after = before.clone();
+    simulate(&instructions, &mut after);
+
+    for cell in i8::MIN..i8::MAX {
+        let cell = cell as isize;
+        let expected = match mapping.iter().find(|(_, to)| to.contains(&cell)) {
+            Some((source, _)) => before[source],
+            None if mapping.contains_key(&cell) => 0,
+            None => before.get(&cell).copied().unwrap_or(0),
+        };
+        if after.get(&cell).copied().unwrap_or(0) != expected {
+            return TestResult::failed();
+        }
+    }
+
+    TestResult::passed()
+}
+
+#[test]
+fn stacks() {
+    // a data stack at 0 and a return stack at 50
+    let run = |effect: &str, data: &[u8], rstack: &[u8]| {
+        let diagram = parse_stacks(effect).unwrap();
+        let bases = [
+            (data.len() - diagram.inputs[0]) as isize,
+            (50 + rstack.len() - diagram.inputs[1]) as isize,
+        ];
+        let instructions = solve_stacks(&diagram, &bases, 40).unwrap();
+
+        let mut tape: HashMap<isize, u8> = data
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i as isize, *v))
+            .chain(
+                rstack
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| (50 + i as isize, *v)),
+            )
+            .collect();
+        simulate(&instructions, &mut tape);
+
+        let read = |base: isize| {
+            (base..)
+                .map(|cell| tape.get(&cell).copied().unwrap_or(0))
+                .take_while(|v| *v != 0)
+                .collect::<Vec<_>>()
+        };
+        (read(0), read(50))
+    };
+
+    // >R
+    assert_eq!(
+        run("a | -- | a", &[1, 2, 3], &[4]),
+        (vec![1, 2], vec![4, 3])
+    );
+    // R>
+    assert_eq!(run("| r -- r |", &[1], &[4, 5]), (vec![1, 5], vec![4]));
+    // 2>R
+    assert_eq!(
+        run("a b | -- | a b", &[1, 2, 3], &[4]),
+        (vec![1], vec![4, 2, 3])
+    );
+    // R@
+    assert_eq!(run("| r -- r | r", &[1], &[4]), (vec![1, 4], vec![4]));
+    // swap the tops of both stacks
+    assert_eq!(
+        run("a b | r -- b r | a", &[1, 2, 3], &[4]),
+        (vec![1, 3, 4], vec![2])
+    );
+
+    assert_eq!(
+        parse_stacks("a | a -- |"),
+        Err(ParseError::SymbolDefinedTwice {
+            symbol: "a".to_string(),
+            first: 0,
+            second: 1
+        })
+    );
+    assert_eq!(
+        parse_stacks("a | b c -- a | c d"),
+        Err(ParseError::SymbolNotDefined {
+            symbol: "d".to_string(),
+            id: 2
+        })
+    );
+}
+
+#[test]
+fn cost() {
+    for effect in [
+        "a b -- b a",
+        "a -- a a a a",
+        "a b c d e f -- c d d f e e b",
+        "a b c -- c",
+    ] {
+        let instructions = solve(&parse(effect).unwrap());
+        let program = generate(instructions.clone(), Brainfuck::new());
+        let cost = Cost::new(&instructions);
+
+        // The pointer model is the same one the brainfuck backend uses
+        assert_eq!(cost.travel, program.matches(['<', '>']).count());
+
+        let bf = BrainfuckCost::new(&program);
+        assert_eq!(bf.length, program.len());
+        assert_eq!(bf.loops, cost.movs + cost.clears);
+        assert_eq!(bf.worst_case_iterations, bf.loops * 255);
+    }
+
+    assert_eq!(
+        Cost::new(&solve(&parse("a b c d -- d c a b").unwrap())),
+        Cost {
+            movs: 5,
+            clears: 0,
+            temp_uses: 1,
+            travel: 30,
+        }
+    );
+
+    // The layout decides which cell is the temporary cell
+    let diagram = parse("a b c -- c a b").unwrap();
+    for layout in [
+        Layout::downward(),
+        Layout::upward().stride(3).base(4),
+        Layout::downward().stride(2).temp_gap(1),
+    ] {
+        let options = SolveOptions::new().layout(layout);
+        let instructions = solve_with(&diagram, &options);
+        assert_eq!(Cost::with_layout(&instructions, &layout).temp_uses, 1);
+    }
+
+    // Nested loops multiply
+    assert_eq!(
+        BrainfuckCost::new("[[-]]").worst_case_iterations,
+        255 + 255 * 255
+    );
+}
+
+#[test]
+fn optimization() {
+    // Clears of empty cells are dropped and the Tops collapse into the last one
+    assert_eq!(
+        optimize(vec![
+            Instruction::Start { cell: 0 },
+            Instruction::Clear { cell: 1 },
+            Instruction::Top { cell: 1 },
+            Instruction::Clear { cell: 1 },
+            Instruction::Mov {
+                cell: 0,
+                to: vec![1]
+            },
+            Instruction::Clear { cell: 0 },
+            Instruction::Top { cell: 2 },
+            Instruction::Top { cell: 1 },
+        ]),
+        vec![
+            Instruction::Start { cell: 0 },
+            Instruction::Clear { cell: 1 },
+            Instruction::Mov {
+                cell: 0,
+                to: vec![1]
+            },
+            Instruction::Top { cell: 1 },
+        ]
+    );
+
+    // A move through a cell that may not be empty is kept
+    let unknown = vec![
+        Instruction::Mov {
+            cell: 0,
+            to: vec![2],
+        },
+        Instruction::Mov {
+            cell: 2,
+            to: vec![1],
+        },
+    ];
+    assert_eq!(optimize(unknown.clone()), unknown);
+
+    // A move through an empty cell is merged into one move
+    let mov = |cell, to: &[isize]| Instruction::Mov {
+        cell,
+        to: to.to_vec(),
+    };
+    assert_eq!(
+        optimize(vec![
+            Instruction::Start { cell: 1 },
+            Instruction::Clear { cell: 2 },
+            mov(0, &[2]),
+            mov(2, &[1]),
+        ]),
+        vec![
+            Instruction::Start { cell: 1 },
+            Instruction::Clear { cell: 2 },
+            mov(0, &[1]),
+        ]
+    );
+
+    // Unless the value comes back to where it started, or is also kept somewhere else
+    for skipped in [
+        vec![mov(1, &[2]), mov(2, &[1, 0])],
+        vec![mov(1, &[2, 0]), mov(2, &[1])],
+    ] {
+        let instructions = [
+            vec![
+                Instruction::Start { cell: 1 },
+                Instruction::Clear { cell: 2 },
+            ],
+            skipped,
+        ]
+        .concat();
+        assert_eq!(optimize(instructions.clone()), instructions);
+    }
+
+    // A Start at a new cell forgets what is known about the tape
+    let restarted = vec![
+        Instruction::Start { cell: 0 },
+        Instruction::Clear { cell: 1 },
+        Instruction::Top { cell: 0 },
+        Instruction::Start { cell: 1 },
+        Instruction::Clear { cell: 1 },
+    ];
+    assert_eq!(optimize(restarted.clone()), restarted);
+
+    // SWAP SWAP
+    let swap = solve(&parse("a b -- b a").unwrap());
+    let twice = [swap.clone(), swap].concat();
+    let optimized = optimize(twice.clone());
+    assert_eq!(Cost::new(&optimized).movs, Cost::new(&twice).movs);
+    assert_eq!(optimized.len(), twice.len() - 2);
+}
+
+#[quickcheck]
+fn quickcheck_optimize(i: u8, v: Vec<u8>, w: Vec<u8>) -> TestResult {
+    let j = v.len();
+    if i == 0 || *v.iter().max().unwrap_or(&0) >= i || *w.iter().max().unwrap_or(&0) as usize >= j {
+        return TestResult::discard();
+    }
+
+    let first = StackEffectDiagram {
+        inputs: i as usize,
+        mapping: v.into_iter().map(|i| i as usize).collect(),
+    };
+    let second = StackEffectDiagram {
+        inputs: j,
+        mapping: w.into_iter().map(|i| i as usize).collect(),
+    };
+
+    // The second program starts where the first leaves the top of the stack
+    let instructions = [solve(&first), solve(&second)].concat();
+    let optimized = optimize(instructions.clone());
+
+    let mut expected: HashMap<isize, u8> = (0..i as isize).map(|c| (c, c as u8 + 1)).collect();
+    let mut tape = expected.clone();
+    simulate(&instructions, &mut expected);
+    simulate(&optimized, &mut tape);
+    expected.retain(|_, value| *value != 0);
+    tape.retain(|_, value| *value != 0);
+
+    let (before, after) = (Cost::new(&instructions), Cost::new(&optimized));
+    TestResult::from_bool(
+        tape == expected
+            && after.movs + after.clears <= before.movs + before.clears
+            && generate(optimized, Brainfuck::new()).len()
+                <= generate(instructions, Brainfuck::new()).len(),
+    )
+}
+
+#[test]
+fn solution_cache() {
+    let mut cache = SolutionCache::new();
+    let plain = SolveOptions::new();
+    let strided = SolveOptions::new()
+        .layout(Layout::downward().stride(2).temp_gap(1))
+        .assume_zero(-3)
+        .assume_zero(5)
+        .zero_above(false);
+
+    for effect in [
+        "a b -- b a",
+        "a -- a a a a",
+        "a b c --",
+        "--",
+        "a b c d e f -- c d d f e e b",
+    ] {
+        let diagram = parse(effect).unwrap();
+        assert_eq!(cache.solve(&diagram, &plain), solve(&diagram));
+        assert_eq!(
+            cache.solve(&diagram, &strided),
+            solve_with(&diagram, &strided)
+        );
+    }
+    assert_eq!(cache.len(), 10);
+
+    // Renamed symbols are the same diagram
+    cache.solve(&parse("x y -- y x").unwrap(), &plain);
+    assert_eq!(cache.len(), 10);
+
+    let mut file = Vec::new();
+    cache.save(&mut file).unwrap();
+    let loaded = SolutionCache::load(&file[..]).unwrap();
+    assert_eq!(loaded, cache);
+
+    // Saving is deterministic
+    let mut again = Vec::new();
+    loaded.save(&mut again).unwrap();
+    assert_eq!(file, again);
+
+    let error = SolutionCache::load("2:1,0\tnonsense\tS1".as_bytes()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(SolutionCache::load("".as_bytes()).unwrap().is_empty());
+
+    // Layouts the builder would reject are rejected when they are loaded too
+    for layout in ["0,1,1", "0,-2,3", "0,2,0"] {
+        let line = format!(
+            "1:0\tzeroed= zero_above=true clear_dropped=true layout={}\tS0 T0",
+            layout
+        );
+        let error = SolutionCache::load(line.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+    let line = "1:0\tzeroed= zero_above=true clear_dropped=true layout=0,2,1\tS0 T0";
+    assert_eq!(SolutionCache::load(line.as_bytes()).unwrap().len(), 1);
+}
+
+/// Runs a sequence of Forth words on a stack whose top is the last item
+fn run_forth(words: &[String], stack: &mut Vec<usize>) {
+    for word in words {
+        let top = stack.len() - 1;
+        match word.split_once(' ') {
+            Some((n, "PICK")) => stack.push(stack[top - n.parse::<usize>().unwrap()]),
+            Some((n, "ROLL")) => {
+                let item = stack.remove(top - n.parse::<usize>().unwrap());
+                stack.push(item);
+            }
+            _ => match word.as_str() {
+                "SWAP" => stack.swap(top, top - 1),
+                "DUP" => stack.push(stack[top]),
+                "OVER" => stack.push(stack[top - 1]),
+                "ROT" => {
+                    let item = stack.remove(top - 2);
+                    stack.push(item);
+                }
+                "DROP" => {
+                    stack.pop();
+                }
+                "NIP" => {
+                    stack.remove(top - 1);
+                }
+                "TUCK" => stack.insert(top - 1, stack[top]),
+                word => panic!("unknown word {}", word),
+            },
+        }
+    }
+}
+
+#[test]
+fn forth_words() {
+    for (effect, words) in [
+        ("a b -- b a", "SWAP"),
+        ("a b -- a b a b", "OVER OVER"),
+        ("a b c -- c", "NIP NIP"),
+        ("a b -- b a b", "TUCK"),
+        ("a b c -- b c a", "ROT"),
+        ("a b c -- a b c a", "2 PICK"),
+        ("a -- a a a a", "DUP DUP DUP"),
+        ("a b --", "DROP DROP"),
+    ] {
+        assert_eq!(
+            forth(&parse(effect).unwrap()).words.join(" "),
+            words,
+            "{}",
+            effect
+        );
+    }
+
+    // The shortest sequences for larger diagrams, counting the depths of PICK and ROLL
+    for (effect, tokens) in [
+        ("a b c d -- d c b a", 4),
+        ("a b c d -- d c b a d c b a", 12),
+        ("a b c d e -- e d c b a", 6),
+        ("a b c d e -- b d a e c c", 6),
+        ("a b c d e f -- f e d c b a", 8),
+        ("a b c d e f -- c d d f e e b", 10),
+    ] {
+        let diagram = parse(effect).unwrap();
+        let ForthWords { words, shortest } = forth(&diagram);
+        assert!(shortest, "{}", effect);
+        let count: usize = words.iter().map(|word| word.split(' ').count()).sum();
+        assert_eq!(count, tokens, "{} {:?}", effect, words);
+
+        let mut stack = (0..diagram.inputs).collect();
+        run_forth(&words, &mut stack);
+        assert_eq!(stack, diagram.mapping);
+    }
+
+    // Too large to search, the longer sequence still applies the diagram
+    let reverse = StackEffectDiagram {
+        inputs: 12,
+        mapping: (0..12).rev().collect(),
+    };
+    let ForthWords { words, shortest } = forth(&reverse);
+    assert!(!shortest);
+    let mut stack = (0..12).collect();
+    run_forth(&words, &mut stack);
+    assert_eq!(stack, reverse.mapping);
+}
+
+#[quickcheck]
+fn quickcheck_forth(i: u8, v: Vec<u8>) -> TestResult {
+    if i == 0 || i > 4 || v.len() > 4 || *v.iter().max().unwrap_or(&0) >= i {
+        return TestResult::discard();
+    }
+
+    let diagram = StackEffectDiagram {
+        inputs: i as usize,
+        mapping: v.into_iter().map(|i| i as usize).collect(),
+    };
+
+    // The words never reach below the inputs
+    let mut stack = vec![usize::MAX; 2];
+    stack.extend(0..diagram.inputs);
+    run_forth(&forth(&diagram).words, &mut stack);
+
+    TestResult::from_bool(
+        stack
+            == apply(
+                &diagram,
+                &[usize::MAX, usize::MAX, 0, 1, 2, 3][..diagram.inputs + 2],
+            ),
+    )
+}
+
+#[test]
+fn primitive_search() {
+    let jvm = [
+        ("swap", "a b -- b a"),
+        ("dup", "a -- a a"),
+        ("dup_x1", "a b -- b a b"),
+        ("dup_x2", "a b c -- c a b c"),
+        ("dup2", "a b -- a b a b"),
+        ("dup2_x1", "a b c -- b c a b c"),
+        ("dup2_x2", "a b c d -- c d a b c d"),
+        ("pop", "a --"),
+        ("pop2", "a b --"),
+    ]
+    .map(|(name, effect)| Primitive::new(name, parse(effect).unwrap(), 1));
+    let lookup = |name: &str| &jvm.iter().find(|p| p.name == name).unwrap().diagram;
+
+    for (effect, expected) in [
+        ("a b c -- b c a", 2),
+        ("a b c -- c b a", 3),
+        ("a b -- b a b a", 2),
+        ("a b c --", 2),
+        ("a b c -- a b c", 0),
+    ] {
+        let target = parse(effect).unwrap();
+        let sequence = search(&jvm, &target, 5).unwrap();
+        assert_eq!(sequence.len(), expected, "{}", effect);
+
+        let mut stack: Vec<usize> = (0..target.inputs).collect();
+        for name in sequence {
+            stack = apply(lookup(name), &stack);
+        }
+        assert_eq!(stack, target.mapping);
+    }
+
+    // Cheaper sequences win over shorter ones
+    let costly = [
+        Primitive::new("rot", parse("a b c -- b c a").unwrap(), 3),
+        Primitive::new("swap", parse("a b -- b a").unwrap(), 1),
+        Primitive::new("swap_under", parse("a b c -- b a c").unwrap(), 1),
+    ];
+    let target = parse("a b c -- b c a").unwrap();
+    assert_eq!(search(&costly, &target, 5), Ok(vec!["swap_under", "swap"]));
+    assert_eq!(search(&costly[..1], &target, 5), Ok(vec!["rot"]));
+    // The cheaper sequence is too long
+    assert_eq!(search(&costly, &target, 1), Ok(vec!["rot"]));
+
+    // The stack may have to grow well past the inputs and outputs on the way
+    let tall = [
+        Primitive::new("dup", parse("a -- a a").unwrap(), 1),
+        Primitive::new("fold", parse("a b c d e -- b a").unwrap(), 1),
+    ];
+    assert_eq!(
+        search(&tall, &parse("a b -- b a").unwrap(), 5),
+        Ok(vec!["dup", "dup", "dup", "fold"])
+    );
+
+    // Too deep
+    assert_eq!(
+        search(&jvm, &parse("a b c d -- d c b a").unwrap(), 1),
+        Err(SearchError::NotExpressible { depth: 1 })
+    );
+    assert_eq!(
+        search(&[], &parse("a b -- b a").unwrap(), 5),
+        Err(SearchError::NotExpressible { depth: 5 })
+    );
+}
+
+/// A diagram every code generating backend is run on, with the outputs it leaves for the inputs
+/// given by [`backend_input`]
+struct BackendCase {
+    inputs: usize,
+    outputs: Vec<i64>,
+    instructions: Vec<Instruction>,
+}
+
+impl BackendCase {
+    fn generate<M: crate::Model>(&self, model: M) -> M::Output {
+        generate(self.instructions.iter().cloned(), model)
+    }
+
+    /// Generates the case solved for [`Layout::downward`], the mirror image of the upward stack
+    fn generate_downward<M: crate::Model>(&self, model: M) -> M::Output {
+        let mirrored = self
+            .instructions
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Start { cell } => Instruction::Start { cell: -cell },
+                Instruction::Clear { cell } => Instruction::Clear { cell: -cell },
+                Instruction::Mov { cell, to } => Instruction::Mov {
+                    cell: -cell,
+                    to: to.iter().map(|target| -target).collect(),
+                },
+                Instruction::WeightedMov { cell, to } => Instruction::WeightedMov {
+                    cell: -cell,
+                    to: to
+                        .iter()
+                        .map(|&(target, weight)| (-target, weight))
+                        .collect(),
+                },
+                Instruction::Top { cell } => Instruction::Top { cell: -cell },
+            });
+        generate(mirrored, model)
+    }
+}
+
+/// The inputs of [`backend_cases`], negative so that they fill every bit of a cell
+fn backend_input(i: usize) -> i64 {
+    (i as i64 + 1) * -37
+}
+
+fn backend_cases() -> Vec<BackendCase> {
+    let effects = [
+        "a b -- b a",
+        "a -- a a a a",
+        "a b c -- c",
+        "a b c d -- d c a b",
+        "a b c d e f -- c d d f e e b",
+        "a b -- a b a b",
+        "a b c --",
+        "-- ",
+    ];
+    let linear = ["a b -- a+b a*3", "a b c -- 2*a-c b+c+a a", "a b -- a-a b"];
+
+    let plain = effects.iter().map(|effect| {
+        let diagram = parse(effect).unwrap();
+        (LinearDiagram::from(diagram.clone()), solve(&diagram))
+    });
+    let linear = linear.iter().map(|effect| {
+        let diagram = parse_linear(effect).unwrap();
+        let instructions = solve_linear(&diagram);
+        (diagram, instructions)
+    });
+
+    plain
+        .chain(linear)
+        .map(|(diagram, instructions)| BackendCase {
+            inputs: diagram.inputs,
+            outputs: diagram
+                .mapping
+                .iter()
+                .map(|terms| {
+                    terms.iter().fold(0i64, |sum, &(i, weight)| {
+                        sum.wrapping_add(backend_input(i).wrapping_mul(weight as i64))
+                    })
+                })
+                .collect(),
+            instructions,
+        })
+        .collect()
+}
+
+/// Compiles and links C and assembly files with the system compiler and returns what the program
+/// prints, or `None` when there is no compiler
+fn run_c(name: &str, files: &[(&str, &str)]) -> Option<String> {
+    let dir = std::env::temp_dir().join(format!("autoperm-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let binary = dir.join("main");
+    let paths: Vec<_> = files
+        .iter()
+        .map(|(file, source)| {
+            let path = dir.join(file);
+            std::fs::write(&path, source).unwrap();
+            path
+        })
+        .collect();
+
+    let Ok(status) = std::process::Command::new("cc")
+        .arg("-o")
+        .arg(&binary)
+        .args(&paths)
+        .status()
+    else {
+        eprintln!("cc is not installed, skipping");
+        return None;
+    };
+    let sources: Vec<&str> = files.iter().map(|(_, source)| *source).collect();
+    assert!(
+        status.success(),
+        "failed to compile:\n{}",
+        sources.join("\n")
+    );
+
+    let output = std::process::Command::new(&binary).output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
+/// How a function called by [`check_c_backend`] is given the stack
+#[derive(Clone, Copy)]
+enum CStack {
+    /// `cell_t *f(cell_t *sp)` with the stack growing toward higher addresses
+    Up,
+    /// `cell_t *f(cell_t *sp)` with the stack growing toward lower addresses
+    Down,
+    /// `int f(cell_t *stack, int top)`
+    Array,
+}
+
+/// Links `files` with a C program that calls the functions generated for every backend case on a
+/// stack of `bits` wide `cell_t` holding the inputs, and checks the stacks they leave. Each kind
+/// of function is named by its prefix and the index of its case, such as `down3`.
+fn check_c_backend(name: &str, bits: u32, kinds: &[(&str, CStack)], files: &[(&str, &str)]) {
+    let cases = backend_cases();
+    let mask = u64::MAX >> (64 - bits);
+
+    let mut declarations = String::new();
+    let mut calls = String::new();
+    let mut expected = String::new();
+    for (index, case) in cases.iter().enumerate() {
+        let line: String = case
+            .outputs
+            .iter()
+            .map(|&v| format!("{} ", v as u64 & mask))
+            .collect();
+
+        for &(prefix, stack) in kinds {
+            let function = format!("{}{}", prefix, index);
+            let (harness, declaration) = match stack {
+                CStack::Up => ("run_up", format!("cell_t *{}(cell_t *sp);\n", function)),
+                CStack::Down => ("run_down", format!("cell_t *{}(cell_t *sp);\n", function)),
+                CStack::Array => (
+                    "run_array",
+                    format!("int {}(cell_t *stack, int top);\n", function),
+                ),
+            };
+            declarations += &declaration;
+            calls += &format!("{}({}, {});\n", harness, function, case.inputs);
+            expected += &format!("{}\n", line);
+        }
+    }
+
+    let source = format!(
+        r#"#include <stdio.h>
+#include <stdint.h>
+typedef uint{0}_t cell_t;
+{1}
+#define INPUT(i) (cell_t)((i + 1) * -37)
+void run_up(cell_t *(*f)(cell_t *), int inputs) {{
+    cell_t stack[64] = {{0}};
+    for (int i = 0; i < inputs; i++) stack[8 + i] = INPUT(i);
+    cell_t *sp = f(stack + 8 + inputs - 1);
+    for (cell_t *p = stack + 8; p <= sp; p++) printf("%llu ", (unsigned long long)*p);
+    printf("\n");
+}}
+void run_down(cell_t *(*f)(cell_t *), int inputs) {{
+    cell_t stack[64] = {{0}};
+    for (int i = 0; i < inputs; i++) stack[56 - i] = INPUT(i);
+    cell_t *sp = f(stack + 56 - inputs + 1);
+    for (cell_t *p = stack + 56; p >= sp; p--) printf("%llu ", (unsigned long long)*p);
+    printf("\n");
+}}
+void run_array(int (*f)(cell_t *, int), int inputs) {{
+    cell_t stack[64] = {{0}};
+    for (int i = 0; i < inputs; i++) stack[8 + i] = INPUT(i);
+    int top = f(stack, 8 + inputs - 1);
+    for (int p = 8; p <= top; p++) printf("%llu ", (unsigned long long)stack[p]);
+    printf("\n");
+}}
+int main(void) {{
+{2}return 0;
+}}
+"#,
+        bits, declarations, calls
+    );
+
+    let mut files = files.to_vec();
+    files.push(("main.c", &source));
+    if let Some(output) = run_c(name, &files) {
+        let sources: Vec<&str> = files.iter().map(|(_, source)| *source).collect();
+        assert_eq!(output, expected, "{}", sources.join("\n"));
+    }
+}
+
+#[test]
+fn c() {
+    assert_eq!(autoperm("a b -- a b", C::new()).unwrap(), "");
+
+    // The temporary cell of a downward stack is below it, not at the bottom of the stack
+    let layout = Layout::downward();
+    let solve_down =
+        |effect: &str| solve_with(&parse(effect).unwrap(), &SolveOptions::new().layout(layout));
+    assert_eq!(
+        generate(solve_down("a b -- b"), C::new().layout(layout)),
+        "sp[1] = sp[0];\nsp += 1;\n"
+    );
+    assert_eq!(
+        generate(solve_down("a b -- b a"), C::new().layout(layout)),
+        "cell_t tmp = sp[0];\nsp[0] = sp[1];\nsp[1] = tmp;\n"
+    );
+
+    let mut functions = String::from("#include <stdint.h>\ntypedef uint8_t cell_t;\n");
+    for (index, case) in backend_cases().iter().enumerate() {
+        functions += &format!(
+            "cell_t *up{}(cell_t *sp) {{\n{}return sp;\n}}\n",
+            index,
+            case.generate(C::new())
+        );
+        functions += &format!(
+            "cell_t *down{}(cell_t *sp) {{\n{}return sp;\n}}\n",
+            index,
+            case.generate_downward(C::new().layout(Layout::downward()))
+        );
+        functions += &format!(
+            "int array{}(cell_t *stack, int top) {{\n{}return top;\n}}\n",
+            index,
+            case.generate(C::new().array("stack", "top"))
+        );
+    }
+
+    let kinds = [
+        ("up", CStack::Up),
+        ("down", CStack::Down),
+        ("array", CStack::Array),
+    ];
+    check_c_backend("c", 8, &kinds, &[("shuffle.c", &functions)]);
+}
+
+#[test]
+fn x86_64() {
+    let code = |effect: &str| autoperm(effect, X86_64::new()).unwrap();
+
+    assert_eq!(code("a b -- a b"), "");
+    assert_eq!(
+        code("a -- a a"),
+        "mov rcx, qword [rbx]\nmov qword [rbx + 8], rcx\nmov qword [rbx], rcx\nadd rbx, 8\n"
+    );
+    assert_eq!(
+        code("a b -- b"),
+        "mov rax, qword [rbx]\nmov qword [rbx - 8], rax\nsub rbx, 8\n"
+    );
+
+    // The GNU assembler reads the Intel syntax of NASM once the operand sizes are followed by
+    // `ptr`, so both syntaxes are assembled and run
+    let stack = ".section .note.GNU-stack,\"\",@progbits\n";
+    let mut gas = format!("{}.text\n", stack);
+    let mut nasm = format!("{}.intel_syntax noprefix\n.text\n", stack);
+    for (index, case) in backend_cases().iter().enumerate() {
+        let up = X86_64::new().base("rdi");
+        let down = up.clone().layout(Layout::downward());
+        for (prefix, code) in [
+            ("up", case.generate(up.clone().syntax(Syntax::Gas))),
+            (
+                "down",
+                case.generate_downward(down.clone().syntax(Syntax::Gas)),
+            ),
+        ] {
+            gas += &format!(
+                ".globl {0}{1}\n{0}{1}:\n{2}movq %rdi, %rax\nret\n",
+                prefix, index, code
+            );
+        }
+        for (prefix, code) in [
+            ("up", case.generate(up.syntax(Syntax::Nasm))),
+            ("down", case.generate_downward(down.syntax(Syntax::Nasm))),
+        ] {
+            nasm += &format!(
+                ".globl nasm_{0}{1}\nnasm_{0}{1}:\n{2}mov rax, rdi\nret\n",
+                prefix,
+                index,
+                code.replace("qword [", "qword ptr [")
+            );
+        }
+    }
+
+    let kinds = [
+        ("up", CStack::Up),
+        ("down", CStack::Down),
+        ("nasm_up", CStack::Up),
+        ("nasm_down", CStack::Down),
+    ];
+    let files = [("gas.s", gas.as_str()), ("nasm.s", nasm.as_str())];
+    check_c_backend("x86_64", 64, &kinds, &files);
+}
+
+/// Runs a module made by [`Wat`] with the inputs on a stack whose bottom is at address 64, or at
+/// address 252 when it grows downward, and returns the stack from the bottom up
+fn run_wat(module: &str, inputs: &[i32], downward: bool) -> Vec<i32> {
+    let wasm = wat::parse_str(module).unwrap_or_else(|e| panic!("{}\n{}", e, module));
+    let engine = wasmi::Engine::default();
+    let module = wasmi::Module::new(&engine, &wasm[..]).unwrap();
+    let mut store = wasmi::Store::new(&engine, ());
+    let instance = wasmi::Linker::new(&engine)
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+
+    // The address of a cell of the stack
+    let address = |i: i32| if downward { 252 - 4 * i } else { 64 + 4 * i };
+
+    let memory = instance.get_memory(&store, "memory").unwrap();
+    for (i, input) in inputs.iter().enumerate() {
+        let address = address(i as i32) as usize;
+        memory
+            .write(&mut store, address, &input.to_le_bytes())
+            .unwrap();
+    }
+
+    let shuffle = instance
+        .get_typed_func::<i32, i32>(&store, "shuffle")
+        .unwrap();
+    let sp = shuffle
+        .call(&mut store, address(inputs.len() as i32 - 1))
+        .unwrap();
+
+    let len = (address(1) - address(0)) / 4 * (sp - address(0)) / 4 + 1;
+    (0..len)
+        .map(|i| {
+            let mut bytes = [0; 4];
+            memory
+                .read(&store, address(i) as usize, &mut bytes)
+                .unwrap();
+            i32::from_le_bytes(bytes)
+        })
+        .collect()
+}
+
+#[test]
+fn wat() {
+    let code = |effect: &str| {
+        let body = autoperm(effect, Wat::new()).unwrap();
+
+        // A body on its own is checked by parsing it as a function
+        let function = format!("(module (func (param $sp i32) {}))", body);
+        wat::parse_str(&function).unwrap_or_else(|e| panic!("{}\n{}", e, function));
+        body
+    };
+
+    assert_eq!(code("a b -- a b"), "");
+    assert_eq!(
+        code("a b c -- c"),
+        "(local $base i32)\n\
+         (local.set $base (i32.sub (local.get $sp) (i32.const 8)))\n\
+         (i32.store (local.get $base) (i32.load offset=8 (local.get $base)))\n\
+         (local.set $sp (i32.sub (local.get $sp) (i32.const 8)))\n"
+    );
+    assert_eq!(
+        code("a b c --"),
+        "(local.set $sp (i32.sub (local.get $sp) (i32.const 12)))\n"
+    );
+
+    for case in backend_cases() {
+        let inputs: Vec<i32> = (0..case.inputs).map(|i| backend_input(i) as i32).collect();
+        let outputs: Vec<i32> = case.outputs.iter().map(|&v| v as i32).collect();
+
+        let module = case.generate(Wat::new().module("shuffle"));
+        assert_eq!(run_wat(&module, &inputs, false), outputs, "{}", module);
+
+        let model = Wat::new().module("shuffle").layout(Layout::downward());
+        let module = case.generate_downward(model);
+        assert_eq!(run_wat(&module, &inputs, true), outputs, "{}", module);
+    }
+}
+
+/// Runs an LLVM IR module with `lli` and returns what it prints, or `None` when `lli` is not
+/// installed
+fn run_lli(name: &str, module: &str) -> Option<String> {
+    let Ok(version) = std::process::Command::new("lli").arg("--version").output() else {
+        eprintln!("lli is not installed, skipping");
+        return None;
+    };
+
+    let path = std::env::temp_dir().join(format!("autoperm-{}-{}.ll", name, std::process::id()));
+    std::fs::write(&path, module).unwrap();
+
+    // Opaque pointers became the default in LLVM 15
+    let version = String::from_utf8(version.stdout).unwrap();
+    let major: Option<u32> = version
+        .split("version ")
+        .nth(1)
+        .and_then(|v| v.split('.').next())
+        .and_then(|v| v.parse().ok());
+    let mut command = std::process::Command::new("lli");
+    if major.is_some_and(|major| major < 15) {
+        command.arg("-opaque-pointers");
+    }
+
+    let output = command.arg(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        output.status.success(),
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stderr),
+        module
+    );
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn llvm_ir() {
+    let code = |effect: &str| autoperm(effect, LlvmIr::new()).unwrap();
+
+    assert_eq!(
+        code("a b -- a b"),
+        "define ptr @shuffle(ptr %sp) {\nentry:\n  ret ptr %sp\n}\n"
+    );
+    assert_eq!(
+        code("a b c -- c"),
+        "define ptr @shuffle(ptr %sp) {\n\
+         entry:\n  \
+           %v2 = load i64, ptr %sp\n  \
+           %p0 = getelementptr i64, ptr %sp, i64 -2\n  \
+           store i64 %v2, ptr %p0\n  \
+           %top = getelementptr i64, ptr %sp, i64 -2\n  \
+           ret ptr %top\n\
+         }\n"
+    );
+
+    let mut module = String::from(
+        r#"@format = private constant [6 x i8] c"%lld \00"
+@newline = private constant [2 x i8] c"\0A\00"
+declare i32 @printf(ptr, ...)
+
+define void @print(ptr %from, ptr %to) {
+entry:
+  br label %loop
+loop:
+  %p = phi ptr [ %from, %entry ], [ %next, %body ]
+  %done = icmp ugt ptr %p, %to
+  br i1 %done, label %end, label %body
+body:
+  %v = load i64, ptr %p
+  call i32 (ptr, ...) @printf(ptr @format, i64 %v)
+  %next = getelementptr i64, ptr %p, i64 1
+  br label %loop
+end:
+  call i32 (ptr, ...) @printf(ptr @newline)
+  ret void
+}
+"#,
+    );
+    let mut main = String::from("define i32 @main() {\n");
+    let mut expected = String::new();
+
+    for (index, case) in backend_cases().iter().enumerate() {
+        let name = format!("f{}", index);
+        module += &case.generate(LlvmIr::new().name(&name));
+
+        main += &format!("  %stack{} = alloca [16 x i64]\n", index);
+        for i in 0..case.inputs {
+            main += &format!(
+                "  %input{0}.{1} = getelementptr i64, ptr %stack{0}, i64 {2}\n  \
+                 store i64 {3}, ptr %input{0}.{1}\n",
+                index,
+                i,
+                i + 4,
+                backend_input(i)
+            );
+        }
+        main += &format!(
+            "  %bottom{0} = getelementptr i64, ptr %stack{0}, i64 4\n  \
+             %sp{0} = getelementptr i64, ptr %stack{0}, i64 {1}\n  \
+             %top{0} = call ptr @{2}(ptr %sp{0})\n  \
+             call void @print(ptr %bottom{0}, ptr %top{0})\n",
+            index,
+            case.inputs + 3,
+            name
+        );
+        let line: String = case.outputs.iter().map(|v| format!("{} ", v)).collect();
+        expected += &format!("{}\n", line);
+    }
+
+    module += &main;
+    module += "  ret i32 0\n}\n";
+
+    if let Some(output) = run_lli("llvm", &module) {
+        assert_eq!(output, expected, "{}", module);
+    }
+}
+
+#[test]
+fn dot() {
+    // The complicated example from explanation.md
+    let diagram = parse("a b c d e f g -- c b a e e d").unwrap();
+
+    assert_eq!(
+        to_dot(&diagram),
+        "digraph {
+    node [shape = circle]
+    subgraph cluster_0 {
+        label = \"cycle\"
+        style = dashed
+        0
+        2
+    }
+    1
+    3
+    4
+    5
+    6
+    0 -> 2 [style = bold]
+    1 -> 1 [style = bold]
+    2 -> 0 [style = bold]
+    3 -> 5
+    4 -> 4 [style = bold]
+    4 -> 3
+}
+"
+    );
+
+    // 2 is parked in the temporary cell, 0 takes its place and 1 is left alone
+    let ordered = to_dot_ordered(&diagram);
+    assert!(ordered.contains("        2 [xlabel = \"1\"]\n"));
+    assert!(ordered.contains("        0 [xlabel = \"2\"]\n"));
+    assert!(ordered.contains("    1\n"));
+    assert!(ordered.contains("    6 [xlabel = \"8\"]\n"));
+
+    // Every cycle gets its own cluster
+    let dot = to_dot(&parse("a b c d -- b a d c").unwrap());
+    assert!(dot.contains("cluster_0") && dot.contains("cluster_1"));
+    assert_eq!(
+        to_dot(&parse("--").unwrap()),
+        "digraph {\n    node [shape = circle]\n}\n"
+    );
+}
+
+#[test]
+fn listing() {
+    // The complicated example from explanation.md
+    let listing = autoperm("a b c d e f g -- c b a e e d", Listing::new()).unwrap();
+    assert_eq!(
+        listing,
+        "START 6\nMOV 2 -> {T}\nMOV 0 -> {2}\nMOV T -> {0}\nCLEAR 5\nMOV 3 -> {5}\n\
+         MOV 4 -> {T}\nMOV T -> {4, 3}\nCLEAR 6\nTOP 5\n"
+    );
+
+    let linear = generate(
+        solve_linear(&parse_linear("a b -- a-b b*2").unwrap()),
+        Listing::new(),
+    );
+    assert!(linear.contains("*-1"), "{}", linear);
+
+    // Without a stack the temporary cell is just a number
+    let mapping = HashMap::from([(3, vec![-10]), (-10, vec![3])]);
+    let instructions = solve_mapping(&mapping, 4).unwrap();
+    let listing = generate(instructions.clone(), Listing::new());
+    assert_eq!(listing, "MOV 3 -> {4}\nMOV -10 -> {3}\nMOV 4 -> {-10}\n");
+    assert_eq!(parse_listing(&listing), Ok(instructions));
+
+    assert_eq!(
+        parse_listing("START 2\n\n  // comment\nMOV 1 -> {T1*2, 0}\nTOP 2"),
+        Ok(vec![
+            Instruction::Start { cell: 2 },
+            Instruction::WeightedMov {
+                cell: 1,
+                to: vec![(4, 2), (0, 1)]
+            },
+            Instruction::Top { cell: 2 },
+        ])
+    );
+    assert_eq!(
+        parse_listing("MOV 0 {1}"),
+        Err(ListingError::InvalidLine {
+            line: 1,
+            text: "MOV 0 {1}".to_string()
+        })
+    );
+    assert_eq!(
+        parse_listing("START 1\nCLEAR x\nTOP 1"),
+        Err(ListingError::UnknownSymbol {
+            line: 2,
+            symbol: "x".to_string()
+        })
+    );
+
+    // Names replace the cells of the inputs, but not the temporary cells
+    let names = ["a", "b", "c", "d", "e", "f", "g"];
+    let instructions = solve(&parse("a b c d e f g -- c b a e e d").unwrap());
+    let listing = generate(instructions.clone(), Listing::new().names(&names));
+    assert_eq!(
+        listing,
+        "START 6\nMOV c -> {T}\nMOV a -> {c}\nMOV T -> {a}\nCLEAR f\nMOV d -> {f}\n\
+         MOV e -> {T}\nMOV T -> {e, d}\nCLEAR g\nTOP 5\n"
+    );
+    assert_eq!(
+        parse_listing_with(&listing, &Listing::new().names(&names)),
+        Ok(instructions)
+    );
+    assert_eq!(
+        parse_listing_with(
+            "START 1\nMOV x -> {T*2, y}\nTOP 1",
+            &Listing::new().names(&["x", "y"])
+        ),
+        Ok(vec![
+            Instruction::Start { cell: 1 },
+            Instruction::WeightedMov {
+                cell: 0,
+                to: vec![(2, 2), (1, 1)]
+            },
+            Instruction::Top { cell: 1 },
+        ])
+    );
+
+    // The temporary cells of other layouts are where the layout puts them, past the lowest cell
+    // of a stack that grows downward, and the inputs keep their names
+    let layout = Layout::downward();
+    let instructions = solve_with(
+        &parse("a b c -- c a a").unwrap(),
+        &SolveOptions::new().layout(layout),
+    );
+    let model = Listing::new().names(&["a", "b", "c"]).layout(layout);
+    let listing = generate(instructions.clone(), model.clone());
+    assert_eq!(
+        listing,
+        "START -2\nCLEAR b\nMOV c -> {T}\nMOV a -> {c, b}\nMOV T -> {a}\nTOP -2\n"
+    );
+    assert_eq!(parse_listing_with(&listing, &model), Ok(instructions));
+}
+
+#[quickcheck]
+fn quickcheck_listing(i: u8, v: Vec<u8>, w: Vec<(u8, i8)>) -> TestResult {
+    if i == 0 || *v.iter().max().unwrap_or(&0) >= i || w.iter().any(|(j, _)| *j >= i) {
+        return TestResult::discard();
+    }
+
+    let diagram = StackEffectDiagram {
+        inputs: i as usize,
+        mapping: v.into_iter().map(|i| i as usize).collect(),
+    };
+
+    // One more output that is a sum of weighted inputs
+    let mut linear = LinearDiagram::from(diagram.clone());
+    let mut sum = std::collections::BTreeMap::new();
+    for (j, weight) in w {
+        *sum.entry(j as usize).or_insert(0) += weight as isize;
+    }
+    linear
+        .mapping
+        .push(sum.into_iter().filter(|(_, w)| *w != 0).collect());
+
+    TestResult::from_bool([solve(&diagram), solve_linear(&linear)].into_iter().all(
+        |instructions| {
+            let listing = generate(instructions.clone(), Listing::new());
+            parse_listing(&listing) == Ok(instructions)
+        },
+    ))
+}
+
+#[test]
+fn trace() {
+    // The walk through in the README
+    let trace = autoperm("a b -- a b a b", Trace::new()).unwrap();
+    assert_eq!(
+        trace,
+        "<[->>>>+<<<<]>>>>[-<<+<<+>>>>]<<<[->>>+<<<]>>>[-<+<<+>>>]<
+
+# the tape
+ 0 *1  2  3  T
+ a  b  0  0  0
+
+<[->>>>+<<<<]      0 → {T}
+*0  1  2  3  T
+ 0  b  0  0  a
+
+>>>>[-<<+<<+>>>>]  T → {2 0}
+ 0  1  2  3 *T
+ a  b  a  0  0
+
+<<<[->>>+<<<]      1 → {T}
+ 0 *1  2  3  T
+ a  0  a  0  b
+
+>>>[-<+<<+>>>]     T → {3 1}
+ 0  1  2  3 *T
+ a  b  a  b  0
+
+<
+ 0  1  2 *3  T
+ a  b  a  b  0
+"
+    );
+
+    // The trace ends with the same program the Brainfuck model generates
+    for effect in ["a b c d e f -- c d d f e e b", "a b c -- c", "a -- a a a a"] {
+        let trace = autoperm(effect, Trace::new()).unwrap();
+        assert_eq!(
+            trace.lines().next(),
+            Some(autoperm_bf(effect).unwrap().as_str())
+        );
+    }
+
+    // Columns grow to fit sums
+    let trace = autoperm_linear("a b -- a+b a-2*b", Trace::new()).unwrap();
+    assert!(
+        trace.ends_with("\n     0 *    1      T     T1\n   a+b  a-2*b      0      0\n"),
+        "{}",
+        trace
+    );
+
+    // The temporary cell of a downward stack is below it, and the inputs keep their names
+    let layout = Layout::downward();
+    let instructions = solve_with(
+        &parse("a b -- b a").unwrap(),
+        &SolveOptions::new().layout(layout),
+    );
+    assert_eq!(
+        generate(instructions, Trace::new().layout(layout)),
+        "[-<+>]>[-<+>]<<[->>+<<]>
+
+# the tape
+  T *-1   0
+  0   b   a
+
+[-<+>]      -1 → {T}
+  T *-1   0
+  b   0   a
+
+>[-<+>]     0 → {-1}
+  T  -1 * 0
+  b   a   0
+
+<<[->>+<<]  T → {0}
+* T  -1   0
+  0   a   b
+
+>
+  T *-1   0
+  0   a   b
+"
+    );
+}
+
+#[test]
+fn execute() {
+    let mut stack: Vec<String> = ["x", "a", "b", "c"].map(String::from).to_vec();
+    let result = autoperm("a b c -- c a a", Execute::new(&mut stack)).unwrap();
+    assert_eq!(result, &["x", "c", "a", "a"]);
+
+    autoperm("a b c --", Execute::new(&mut stack)).unwrap();
+    assert_eq!(stack, ["x"]);
+
+    // Without a stack the cells are indices
+    let mut cells = vec![1, 2, 3];
+    let mapping = HashMap::from([(0, vec![2]), (2, vec![0, 1])]);
+    generate(
+        solve_mapping(&mapping, 3).unwrap(),
+        Execute::new(&mut cells),
+    );
+    assert_eq!(cells, [3, 3, 1]);
+
+    // Only the temporary cell is removed, not the cells the values were moved to
+    let mut cells = vec![1, 2];
+    let mapping = HashMap::from([(0, vec![3]), (1, vec![0]), (3, vec![1])]);
+    generate(
+        solve_mapping(&mapping, 5).unwrap(),
+        Execute::new(&mut cells),
+    );
+    assert_eq!(cells, [2, 0, 0, 1]);
+}
+
+#[quickcheck]
+fn quickcheck_execute(i: u8, v: Vec<u8>) -> TestResult {
+    if i == 0 || *v.iter().max().unwrap_or(&0) >= i {
+        return TestResult::discard();
+    }
+
+    let diagram = StackEffectDiagram {
+        inputs: i as usize,
+        mapping: v.into_iter().map(|i| i as usize).collect(),
+    };
+    let stack: Vec<usize> = (0..diagram.inputs + 2).map(|i| i * 7 + 1).collect();
+
+    let mut executed = stack.clone();
+    generate(solve(&diagram), Execute::new(&mut executed));
+
+    TestResult::from_bool(executed == apply(&diagram, &stack))
+}
+
+/// Every combination of the Brainfuck dialect options, indexed by the bits of `flags`
+fn brainfuck_options(flags: u8) -> BrainfuckOptions {
+    let options = BrainfuckOptions::new().increment_first(flags & 1 != 0);
+    let options = match flags & 2 {
+        0 => options,
+        _ => options.clear(ClearLoop::Increment),
+    };
+    let options = match flags & 4 {
+        0 => options,
+        _ => options.pointer(PointerPosition::AboveTop),
+    };
+    match flags & 8 {
+        0 => options,
+        _ => options.layout(Layout::downward()),
+    }
+}
+
+#[test]
+fn dialects() {
+    let code = |effect: &str, options: BrainfuckOptions| {
+        autoperm(effect, Brainfuck::with_options(options)).unwrap()
+    };
+
+    assert_eq!(
+        code("a b c -- c", BrainfuckOptions::new()),
+        autoperm_bf("a b c -- c").unwrap()
+    );
+    assert_eq!(
+        code("a b -- b a", BrainfuckOptions::new().increment_first(true)),
+        "[>+<-]<[>+<-]>>[<<+>>-]<"
+    );
+    assert_eq!(
+        code(
+            "a b c -- c",
+            BrainfuckOptions::new().clear(ClearLoop::Increment)
+        ),
+        "<<[+]>[+]>[-<<+>>]<<"
+    );
+    assert_eq!(
+        code(
+            "a -- a a",
+            BrainfuckOptions::new().pointer(PointerPosition::AboveTop)
+        ),
+        "<[->>+<<]>>[-<+<+>>]"
+    );
+    assert_eq!(
+        code("a b c -- c", BrainfuckOptions::new().cell_bits(None)),
+        "<<[-]>[-]>[-<<+>>]<<"
+    );
+
+    // Above the top of a downward stack is the cell below it
+    let layout = SolveOptions::new().layout(Layout::downward());
+    let options = BrainfuckOptions::new()
+        .pointer(PointerPosition::AboveTop)
+        .layout(Layout::downward());
+    let program = generate(
+        solve_with(&parse("a -- a a").unwrap(), &layout),
+        Brainfuck::with_options(options),
+    );
+    assert_eq!(program, ">[-<<+>>]<<[->+>+<<]");
+
+    // Weights wrap around with the cells
+    let linear = |bits: Option<u32>| {
+        let options = BrainfuckOptions::new().cell_bits(bits);
+        autoperm_linear("a -- a*255", Brainfuck::with_options(options)).unwrap()
+    };
+    assert_eq!(linear(Some(8)), "[->+<]>[-<->]<");
+    assert_eq!(linear(Some(1)), "[->+<]>[-<+>]<");
+    assert_eq!(linear(None), format!("[->+<]>[-<{}>]<", "+".repeat(255)));
+
+    let program = autoperm_bf("a b -- b a").unwrap();
+    assert_eq!(
+        BrainfuckCost::with_cell_bits(&program, Some(8)),
+        BrainfuckCost::new(&program)
+    );
+    assert_eq!(
+        BrainfuckCost::with_cell_bits(&program, Some(1)).worst_case_iterations,
+        3
+    );
+    assert_eq!(
+        BrainfuckCost::with_cell_bits(&program, None).worst_case_iterations,
+        usize::MAX
+    );
+
+    let result = std::panic::catch_unwind(|| {
+        Brainfuck::with_options(
+            BrainfuckOptions::new()
+                .clear(ClearLoop::Increment)
+                .cell_bits(None),
+        )
+    });
+    assert!(result.is_err());
+
+    // The same conventions apply to weighted moves
+    let program = autoperm_linear(
+        "a -- a*3",
+        Brainfuck::with_options(BrainfuckOptions::new().increment_first(true)),
+    );
+    assert_eq!(program, Ok("[>+<-]>[<+++>-]<".to_string()));
+}
+
+#[quickcheck]
+fn quickcheck_dialects(i: u8, v: Vec<u8>, flags: u8) -> TestResult {
+    if i == 0 || *v.iter().max().unwrap_or(&0) >= i {
+        return TestResult::discard();
+    }
+
+    let diagram = StackEffectDiagram {
+        inputs: i as usize,
+        mapping: v.into_iter().map(|i| i as usize).collect(),
+    };
+    let options = brainfuck_options(flags);
+    let function = match flags & 8 {
+        0 => generate(solve(&diagram), Brainfuck::with_options(options)),
+        _ => {
+            // A downward stack is the mirror image of an upward one
+            let layout = SolveOptions::new().layout(Layout::downward());
+            let function = generate(
+                solve_with(&diagram, &layout),
+                Brainfuck::with_options(options),
+            );
+            function
+                .chars()
+                .map(|c| match c {
+                    '<' => '>',
+                    '>' => '<',
+                    c => c,
+                })
+                .collect()
+        }
+    };
+
+    // A program that starts and ends above the stack runs one cell higher
+    let function = match flags & 4 {
+        0 => function,
+        _ => format!(">{}<", function),
+    };
+
+    TestResult::from_bool(test_function(&function, diagram.inputs, 0, |input| {
+        diagram.mapping.iter().map(|&i| input[i]).collect()
+    }))
+}
+
+#[test]
+fn rust() {
+    let code = |effect: &str| autoperm(effect, Rust::new()).unwrap();
+
+    assert_eq!(code("a b -- a b"), "");
+    assert_eq!(
+        code("a b c --"),
+        "{\n    let base = v.len() - 3;\n    v.truncate(base);\n}\n"
+    );
+    assert_eq!(
+        code("a -- a a"),
+        "{\n    let base = v.len() - 1;\n    v.push(v[base].clone());\n}\n"
+    );
+    assert_eq!(
+        code("a b c -- c c"),
+        "{\n    let base = v.len() - 3;\n    v.swap(base + 2, base);\n    v[base + 1] = v[base].clone();\n    v.truncate(base + 2);\n}\n"
+    );
+
+    // The slice is long enough, nothing is dropped
+    assert_eq!(
+        autoperm("a b c -- b", Rust::slice("s", "i")),
+        Ok("s.swap(i - 1, i - 2);\ni -= 2;\n".to_string())
+    );
+}
+
+#[test]
+fn error_spans() {
+    fn span(effect: &str, error: ParseError) -> Option<&str> {
+        error.span(effect).map(|range| &effect[range])
+    }
+
+    assert_eq!(
+        span("a b -- a c", parse("a b -- a c").unwrap_err()),
+        Some("c")
+    );
+    assert_eq!(
+        span("a b a -- a", parse("a b a -- a").unwrap_err()),
+        Some("a")
+    );
+    assert_eq!(
+        parse("a b a -- a").unwrap_err().span("a b a -- a"),
+        Some(4..5)
+    );
+    assert_eq!(
+        parse("a -- a -- a").unwrap_err().span("a -- a -- a"),
+        Some(7..9)
+    );
+    assert_eq!(parse("a b").unwrap_err().span("a b"), None);
+
+    let effect = "a -- a+b";
+    assert_eq!(span(effect, parse_linear(effect).unwrap_err()), Some("a+b"));
+    let effect = "a|b -- a | c";
+    assert_eq!(span(effect, parse_stacks(effect).unwrap_err()), Some("c"));
+}
+
+/// Applies a diagram to the top of a stack of symbols
+fn apply(diagram: &StackEffectDiagram, stack: &[usize]) -> Vec<usize> {
+    let split = stack.len() - diagram.inputs;
+    let mut result = stack[..split].to_vec();
+    result.extend(diagram.mapping.iter().map(|&i| stack[split + i]));
+    result
+}
+
+#[test]
+fn sequences() {
+    let words = |effects: &[&str]| {
+        effects
+            .iter()
+            .map(|effect| parse(effect).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    // SWAP OVER ROT
+    let fused = compose(&words(&["a b -- b a", "a b -- a b a", "a b c -- b c a"]));
+    assert_eq!(fused, parse("a b -- a b b").unwrap());
+    assert!(test_stackeffect(&fused));
+
+    // DROP SWAP reaches below the first diagram
+    let fused = compose(&words(&["a --", "a b -- b a"]));
+    assert_eq!(fused, parse("a b c -- b a").unwrap());
+    assert!(test_stackeffect(&fused));
+
+    // SWAP SWAP is the identity
+    let fused = compose(&words(&["a b -- b a", "a b -- b a"]));
+    assert_eq!(generate(solve(&fused), Brainfuck::new()), "");
+
+    assert_eq!(compose(&[]), parse("--").unwrap());
+}
+
+#[quickcheck]
+fn quickcheck_compose(i: u8, v: Vec<u8>, j: u8, w: Vec<u8>) -> TestResult {
+    if i == 0 || j == 0 || *v.iter().max().unwrap_or(&0) >= i || *w.iter().max().unwrap_or(&0) >= j
+    {
+        return TestResult::discard();
+    }
+
+    let first = StackEffectDiagram {
+        inputs: i as usize,
+        mapping: v.into_iter().map(|i| i as usize).collect(),
+    };
+    let second = StackEffectDiagram {
+        inputs: j as usize,
+        mapping: w.into_iter().map(|i| i as usize).collect(),
+    };
+    let fused = first.then(&second);
+
+    let stack: Vec<usize> = (0..fused.inputs).collect();
+    let split = fused.inputs - first.inputs;
+    let mut middle = stack[..split].to_vec();
+    middle.extend(apply(&first, &stack[split..]));
+
+    TestResult::from_bool(apply(&second, &middle) == apply(&fused, &stack))
+}
+
 #[quickcheck]
 fn quickcheck(i: u8, v: Vec<u8>) -> TestResult {
     if i == 0 || *v.iter().max().unwrap_or(&0) >= i {